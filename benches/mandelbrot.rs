@@ -5,23 +5,68 @@ extern crate test;
 #[macro_use]
 extern crate rust_mpfr;
 extern crate mandelrust;
+extern crate num;
 
 use test::Bencher;
 use mandelrust::mandelbrot::*;
 use rust_mpfr::mpfr::*;
+use num::complex::Complex64;
+
+/// Point near the cusp at -0.75, outside the cardioid and the period-2 bulb,
+/// that escapes only after 902 iterations.
+const X: &str = "-0.75";
+const Y: &str = "0.0035";
 
 #[bench]
 fn bench_iterate_float(b: &mut Bencher) {
     let max = 1000;
+    let x: f64 = X.parse().unwrap();
+    let y: f64 = Y.parse().unwrap();
 
-    b.iter(|| { iterate::<f64>(-0.5f64, 0f64, max) });
+    b.iter(|| { iterate::<f64>(x, y, max) });
 }
 
 #[bench]
 fn bench_iterate_mpfr(b: &mut Bencher) {
     let max = 1000;
-    let m1 = Mpfr::new_from_str("-0.5", 10).unwrap();
-    let m2 = Mpfr::new_from_str("0.0", 10).unwrap();
+    let m1 = Mpfr::new_from_str(X, 10).unwrap();
+    let m2 = Mpfr::new_from_str(Y, 10).unwrap();
+
+    b.iter(|| { iterate::<Mpfr>(m1.clone(), m2.clone(), max) });
+}
+
+#[bench]
+fn bench_iterate_double_double(b: &mut Bencher) {
+    let max = 1000;
+    let x = DoubleDouble::from(X.parse::<f64>().unwrap());
+    let y = DoubleDouble::from(Y.parse::<f64>().unwrap());
+
+    b.iter(|| { iterate::<DoubleDouble>(x, y, max) });
+}
+
+#[bench]
+fn bench_iterate_mpfr_106(b: &mut Bencher) {
+    let max = 1000;
+    let m1 = Mpfr::new2_from_str(106, X, 10).unwrap();
+    let m2 = Mpfr::new2_from_str(106, Y, 10).unwrap();
+
+    b.iter(|| { iterate::<Mpfr>(m1.clone(), m2.clone(), max) });
+}
+
+#[bench]
+fn bench_iterate_quad_double(b: &mut Bencher) {
+    let max = 1000;
+    let x = QuadDouble::from(X.parse::<f64>().unwrap());
+    let y = QuadDouble::from(Y.parse::<f64>().unwrap());
+
+    b.iter(|| { iterate::<QuadDouble>(x, y, max) });
+}
+
+#[bench]
+fn bench_iterate_mpfr_212(b: &mut Bencher) {
+    let max = 1000;
+    let m1 = Mpfr::new2_from_str(212, X, 10).unwrap();
+    let m2 = Mpfr::new2_from_str(212, Y, 10).unwrap();
 
     b.iter(|| { iterate::<Mpfr>(m1.clone(), m2.clone(), max) });
 }
@@ -34,11 +79,46 @@ fn bench_calculate_all(b: &mut Bencher) {
     b.iter(|| calculate_all_mpfr(c.clone(), max));
 }
 
+#[bench]
+fn bench_calculate_all_f64(b: &mut Bencher) {
+    let max = 1000;
+    let c = CanvasSize::new_from_center(50, 50, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+
+    b.iter(|| calculate_all_f64(c.clone(), max));
+}
+
 #[bench]
 fn bench_make_image(b: &mut Bencher) {
     let max = 1000;
     let c = CanvasSize::new_from_center(50, 50, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
     let data = calculate_all_mpfr(c.clone(), max);
 
-    b.iter(|| make_image(data.clone(), c.clone(), max));
+    b.iter(|| make_image(data.clone(), c.clone()));
+}
+
+/// Reference orbit at the location of examples/orbit.rs, and a pixel offset
+/// from it that escapes after 108093 iterations.
+fn orbit_location(max: u32) -> (ReferenceOrbit, Complex64) {
+    let x = Mpfr::new2_from_str(500, "-1.94156695046089411519723545042405785041614289882129276726563682132425517088701591901639765783026799492137462058533435865811646793455507945", 10).unwrap();
+    let y = Mpfr::new2_from_str(500, "-0.00017301617109765913618843129805085094212526763902256359889382674446838992551267030035919209576862092841923454016429306276671035659004195", 10).unwrap();
+
+    (ReferenceOrbit::new([x, y], max), Complex64::new(1e-130, 0.7e-130))
+}
+
+#[bench]
+fn bench_iterate_perturbed(b: &mut Bencher) {
+    let max = 203416;
+    let (orbit, dc) = orbit_location(max);
+    let bla = BilinearApproximation::none();
+
+    b.iter(|| iterate_perturbed(&orbit, &bla, dc, max));
+}
+
+#[bench]
+fn bench_iterate_perturbed_bla(b: &mut Bencher) {
+    let max = 203416;
+    let (orbit, dc) = orbit_location(max);
+    let bla = BilinearApproximation::new(&orbit, 2e-130);
+
+    b.iter(|| iterate_perturbed(&orbit, &bla, dc, max));
 }
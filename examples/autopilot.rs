@@ -0,0 +1,21 @@
+#[macro_use]
+extern crate rust_mpfr;
+extern crate mandelrust;
+
+use rust_mpfr::mpfr::*;
+use mandelrust::mandelbrot::*;
+
+/// Zooms on its own from the whole set to the zoom given as the argument,
+/// printing every location it visits.
+fn main() {
+    let target = std::env::args().nth(1).unwrap_or("1e100".to_string());
+    let target = Mpfr::new_from_str(target, 10).expect("Wrong literal");
+
+    let canvas = CanvasSize::new_from_center(240, 160, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+    let canvas = canvas.set_prec(canvas.required_prec());
+
+    autopilot(canvas, 1000, &target, calculate_all_delta, |c, max| {
+        let center = c.center();
+        println!("x: {}\ny: {}\nzoom: {}\nmax: {}\n", center[0], center[1], c.get_zoom(), max);
+    });
+}
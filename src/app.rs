@@ -3,6 +3,8 @@ use carboxyl_window::Event::Press;
 use input::Button::{Mouse, Keyboard};
 use input::{MouseButton, Key};
 use image::RgbImage;
+use rust_mpfr::mpfr::Mpfr;
+use num::complex::Complex64;
 use mandelbrot::*;
 
 #[derive(Clone, Copy)]
@@ -14,6 +16,11 @@ pub enum Action {
     PrecisionUp,
     PrecisionDown,
     SwitchGenerator,
+    SwitchColoring,
+    SwitchFractal,
+    ToggleJulia([f64; 2], (u32, u32)),
+    SnapToMinibrot([f64; 2], (u32, u32)),
+    ShowPeriod([f64; 2], (u32, u32)),
 }
 
 pub fn intent(context: Context, event: Event) -> Option<Action> {
@@ -28,6 +35,20 @@ pub fn intent(context: Context, event: Event) -> Option<Action> {
         Press(Keyboard(Key::Home)) => Some(Action::PrecisionUp),
         Press(Keyboard(Key::End)) => Some(Action::PrecisionDown),
         Press(Keyboard(Key::F1)) => Some(Action::SwitchGenerator),
+        Press(Keyboard(Key::F2)) => Some(Action::SwitchColoring),
+        Press(Keyboard(Key::F3)) => Some(Action::SwitchFractal),
+        Press(Keyboard(Key::J)) => {
+            Some(Action::ToggleJulia([context.cursor.position.0, context.cursor.position.1],
+                                     context.window.size))
+        }
+        Press(Keyboard(Key::M)) => {
+            Some(Action::SnapToMinibrot([context.cursor.position.0, context.cursor.position.1],
+                                        context.window.size))
+        }
+        Press(Keyboard(Key::P)) => {
+            Some(Action::ShowPeriod([context.cursor.position.0, context.cursor.position.1],
+                                    context.window.size))
+        }
         _ => None,
     }
 }
@@ -38,55 +59,274 @@ pub struct State {
     canvas: CanvasSize,
     max: u32,
     generator: Generator,
+    coloring: Coloring,
+    fractal: Fractal,
+    julia: Option<Julia>,
+    orbits: OrbitCache,
+}
+
+/// The Julia set shown instead of the Mandelbrot set, and the view to go back to.
+#[derive(Clone)]
+struct Julia {
+    c: [Mpfr; 2],
+    mandelbrot: CanvasSize,
 }
 
 #[derive(Clone, Debug)]
 enum Generator {
+    Auto,
+    F64,
+    DD,
+    QD,
     MPFR,
     DELTA,
 }
 
+impl Generator {
+    /// Resolves `Auto` to `F64` while `f64` can tell the pixels apart, else `DELTA`.
+    fn pick(&self, canvas: &CanvasSize) -> Generator {
+        match *self {
+            Generator::Auto if canvas.required_prec() <= 53 => Generator::F64,
+            Generator::Auto => Generator::DELTA,
+            ref gen => gen.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Coloring {
+    Iterations,
+    Distance,
+    Interior(InteriorColoring),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fractal {
+    Mandelbrot,
+    Multibrot3,
+    Multibrot4,
+    BurningShip,
+    Tricorn,
+    Celtic,
+    Nebulabrot,
+    Newton,
+    Nova,
+}
+
 impl State {
-    fn calc(canvas: CanvasSize, max: u32, gen: Generator) -> State {
-        let v = match gen {
-            Generator::MPFR => calculate_all_mpfr(canvas.clone(), max),
-            Generator::DELTA => calculate_all_delta(canvas.clone(), max),
+    fn calc(canvas: CanvasSize,
+            max: u32,
+            gen: Generator,
+            coloring: Coloring,
+            fractal: Fractal,
+            julia: Option<Julia>,
+            orbits: OrbitCache)
+            -> State {
+        let mut orbits = orbits;
+        let imgbuf = match (julia.as_ref(), fractal) {
+            (Some(julia), _) => julia_image(&canvas, max, &gen, &julia.c),
+            (None, Fractal::Mandelbrot) => {
+                mandelbrot_image(&canvas, max, &gen, coloring, &mut orbits)
+            }
+            (None, Fractal::Multibrot3) => formula_image(&canvas, max, &gen, &Multibrot::new(3)),
+            (None, Fractal::Multibrot4) => formula_image(&canvas, max, &gen, &Multibrot::new(4)),
+            (None, Fractal::BurningShip) => formula_image(&canvas, max, &gen, &BurningShip),
+            (None, Fractal::Tricorn) => formula_image(&canvas, max, &gen, &Tricorn),
+            (None, Fractal::Celtic) => formula_image(&canvas, max, &gen, &Celtic),
+            (None, Fractal::Nebulabrot) => nebulabrot_image(&canvas, max),
+            (None, Fractal::Newton) => newton_image(&canvas, max, &gen, false),
+            (None, Fractal::Nova) => newton_image(&canvas, max, &gen, true),
         };
-        let imgbuf = make_image(v, canvas.clone(), max);
 
         State {
             image: imgbuf,
             canvas: canvas.clone(),
             max: max,
             generator: gen,
+            coloring: coloring,
+            fractal: fractal,
+            julia: julia,
+            orbits: orbits,
+        }
+    }
+
+    /// Recalculates with the same generator, coloring, fractal and Julia set,
+    /// reusing the reference orbit where possible.
+    fn recalc(self, canvas: CanvasSize, max: u32) -> State {
+        State::calc(canvas,
+                    max,
+                    self.generator,
+                    self.coloring,
+                    self.fractal,
+                    self.julia,
+                    self.orbits)
+    }
+
+    /// The pixel of the image under the cursor.
+    fn pixel_at(&self, loc: [f64; 2], win_size: (u32, u32)) -> [f64; 2] {
+        let (x, _) = win_size;
+        let scale_factor = x as f64 / self.image.dimensions().0 as f64;
+        [loc[0] / scale_factor, loc[1] / scale_factor]
+    }
+
+    /// A region a few pixels across around the cursor.
+    fn region_at(&self, loc: [f64; 2], win_size: (u32, u32)) -> CanvasSize {
+        const PIXELS: f64 = 16.0;
+
+        let pixel = self.pixel_at(loc, win_size);
+        self.canvas
+            .move_center_to_pixel(pixel)
+            .zoom(Mpfr::from(self.canvas.pixel_width as f64 / PIXELS))
+    }
+}
+
+fn mandelbrot_image(canvas: &CanvasSize,
+                    max: u32,
+                    gen: &Generator,
+                    coloring: Coloring,
+                    orbits: &mut OrbitCache)
+                    -> RgbImage {
+    match coloring {
+        Coloring::Iterations => {
+            let v = match gen.pick(canvas) {
+                Generator::F64 => calculate_all_f64(canvas.clone(), max),
+                Generator::DD => calculate_all_double_double(canvas.clone(), max),
+                Generator::QD => calculate_all_quad_double(canvas.clone(), max),
+                Generator::MPFR => calculate_all_subdivided::<Mpfr>(canvas.clone(), max),
+                Generator::DELTA | Generator::Auto => {
+                    calculate_all_delta_cached(canvas.clone(), max, orbits)
+                }
+            };
+            make_image(v, canvas.clone())
+        }
+        Coloring::Distance => {
+            let v = match gen.pick(canvas) {
+                Generator::F64 => calculate_all_distance::<f64>(canvas.clone(), max),
+                Generator::DD => calculate_all_distance::<DoubleDouble>(canvas.clone(), max),
+                Generator::QD => calculate_all_distance::<QuadDouble>(canvas.clone(), max),
+                Generator::MPFR => calculate_all_distance::<Mpfr>(canvas.clone(), max),
+                Generator::DELTA | Generator::Auto => {
+                    calculate_all_delta_distance_cached(canvas.clone(), max, orbits)
+                }
+            };
+            make_distance_image(v, canvas.clone())
+        }
+        Coloring::Interior(interior_coloring) => {
+            let (v, interior) = match gen.pick(canvas) {
+                Generator::F64 => calculate_all_interior::<f64>(canvas.clone(), max),
+                Generator::DD => calculate_all_interior::<DoubleDouble>(canvas.clone(), max),
+                Generator::QD => calculate_all_interior::<QuadDouble>(canvas.clone(), max),
+                Generator::MPFR => calculate_all_interior::<Mpfr>(canvas.clone(), max),
+                Generator::DELTA | Generator::Auto => {
+                    calculate_all_delta_interior_cached(canvas.clone(), max, orbits)
+                }
+            };
+            make_interior_image(v, interior, canvas.clone(), interior_coloring)
         }
     }
 }
 
+/// Julia sets are only coloured by iterations, and rendered without perturbation.
+fn julia_image(canvas: &CanvasSize, max: u32, gen: &Generator, c: &[Mpfr; 2]) -> RgbImage {
+    let v = match gen.pick(canvas) {
+        Generator::F64 => calculate_all_julia::<f64>(canvas.clone(), c, max),
+        Generator::DD => calculate_all_julia::<DoubleDouble>(canvas.clone(), c, max),
+        Generator::QD => calculate_all_julia::<QuadDouble>(canvas.clone(), c, max),
+        Generator::MPFR | Generator::DELTA | Generator::Auto => {
+            calculate_all_julia::<Mpfr>(canvas.clone(), c, max)
+        }
+    };
+    make_image(v, canvas.clone())
+}
+
+/// Formulas other than `z² + c` are only coloured by iterations, and rendered
+/// without perturbation.
+fn formula_image<F: Formula>(canvas: &CanvasSize,
+                             max: u32,
+                             gen: &Generator,
+                             formula: &F)
+                             -> RgbImage {
+    let v = match gen.pick(canvas) {
+        Generator::F64 => calculate_all_formula::<f64, _>(canvas.clone(), formula, max),
+        Generator::DD => calculate_all_formula::<DoubleDouble, _>(canvas.clone(), formula, max),
+        Generator::QD => calculate_all_formula::<QuadDouble, _>(canvas.clone(), formula, max),
+        Generator::MPFR | Generator::DELTA | Generator::Auto => {
+            calculate_all_formula::<Mpfr, _>(canvas.clone(), formula, max)
+        }
+    };
+    make_image(v, canvas.clone())
+}
+
+/// The Nebulabrot with limits a hundredth, a tenth and all of `max` in red,
+/// green and blue. Always sampled in `f64`.
+fn nebulabrot_image(canvas: &CanvasSize, max: u32) -> RgbImage {
+    let samples = 16 * canvas.pixel_width as u64 * canvas.pixel_height as u64;
+    let v = calculate_nebulabrot(canvas.clone(),
+                                 [max / 100, max / 10, max],
+                                 samples,
+                                 Sampler::Metropolis);
+    make_density_image(v, canvas.clone())
+}
+
+/// The Newton or Nova fractal of `z³ - 1`, coloured by root. Rendered without
+/// perturbation like the formulas.
+fn newton_image(canvas: &CanvasSize, max: u32, gen: &Generator, nova: bool) -> RgbImage {
+    let one = Complex64::new(1.0, 0.0);
+    let zero = Complex64::new(0.0, 0.0);
+    let p = Polynomial::new(vec![-one, zero, zero, one]);
+    let z0 = [mpfr!(1.0), mpfr!(0.0)];
+
+    let v = match (gen.pick(canvas), nova) {
+        (Generator::F64, false) => calculate_all_newton::<f64>(canvas.clone(), &p, max),
+        (Generator::DD, false) => calculate_all_newton::<DoubleDouble>(canvas.clone(), &p, max),
+        (Generator::QD, false) => calculate_all_newton::<QuadDouble>(canvas.clone(), &p, max),
+        (_, false) => calculate_all_newton::<Mpfr>(canvas.clone(), &p, max),
+        (Generator::F64, true) => calculate_all_nova::<f64>(canvas.clone(), &p, one, &z0, max),
+        (Generator::DD, true) => {
+            calculate_all_nova::<DoubleDouble>(canvas.clone(), &p, one, &z0, max)
+        }
+        (Generator::QD, true) => {
+            calculate_all_nova::<QuadDouble>(canvas.clone(), &p, one, &z0, max)
+        }
+        (_, true) => calculate_all_nova::<Mpfr>(canvas.clone(), &p, one, &z0, max),
+    };
+    make_root_image(v, canvas.clone())
+}
+
 pub type View = RgbImage;
 
 pub fn init(canvas: CanvasSize, max: u32) -> State {
-    State::calc(canvas, max, Generator::MPFR)
+    State::calc(canvas,
+                max,
+                Generator::Auto,
+                Coloring::Iterations,
+                Fractal::Mandelbrot,
+                None,
+                OrbitCache::new())
 }
 
 pub fn update(current: State, action: Action) -> State {
     match action {
         Action::ZoomIn(loc, win_size) => {
-            let (x, _) = win_size;
-            let scale_factor = x as f64 / current.image.dimensions().0 as f64;
-            let scaled_loc: [f64; 2] = [loc[0] / scale_factor, loc[1] / scale_factor];
-            State::calc(current.canvas.move_center_to_pixel(scaled_loc).zoom(mpfr!(8.0)),
-                        current.max, current.generator)
+            let scaled_loc = current.pixel_at(loc, win_size);
+            let (canvas, max) =
+                (current.canvas.move_center_to_pixel(scaled_loc).zoom(mpfr!(8.0)), current.max);
+            current.recalc(canvas, max)
+        }
+        Action::ZoomOut => {
+            let (canvas, max) = (current.canvas.zoom(mpfr!(1.0) / 8.0), current.max);
+            current.recalc(canvas, max)
         }
-        Action::ZoomOut => State::calc(current.canvas.zoom(mpfr!(1.0) / 8.0), current.max, current.generator),
         Action::MaxIterationsUp => {
             println!("Max. iterations: {}", current.max + 1000);
-            State::calc(current.canvas, current.max + 1000, current.generator)
+            let (canvas, max) = (current.canvas.clone(), current.max + 1000);
+            current.recalc(canvas, max)
         }
         Action::MaxIterationsDown => {
             if current.max > 1000 {
                 println!("Max. iterations: {}", current.max - 1000);
-                State::calc(current.canvas, current.max - 1000, current.generator)
+                let (canvas, max) = (current.canvas.clone(), current.max - 1000);
+                current.recalc(canvas, max)
             } else {
                 current
             }
@@ -94,7 +334,8 @@ pub fn update(current: State, action: Action) -> State {
         Action::PrecisionUp => {
             println!("{}", current.canvas.get_prec() * 2);
             let new = current.canvas.set_prec(current.canvas.get_prec() * 2);
-            let a = State::calc(new, current.max, current.generator);
+            let max = current.max;
+            let a = current.recalc(new, max);
             println!("a: {}", a.canvas.center()[0].get_prec());
             println!("b: {}", a.canvas.coordinates([0, 0])[0].get_prec());
             a
@@ -102,15 +343,141 @@ pub fn update(current: State, action: Action) -> State {
         Action::PrecisionDown => {
             println!("{}", current.canvas.get_prec() / 2);
             let new = current.canvas.set_prec(current.canvas.get_prec() / 2);
-            State::calc(new, current.max, current.generator)
+            let max = current.max;
+            current.recalc(new, max)
         },
         Action::SwitchGenerator => {
             let new_gen = match current.generator {
+                Generator::Auto => Generator::F64,
+                Generator::F64 => Generator::DD,
+                Generator::DD => Generator::QD,
+                Generator::QD => Generator::MPFR,
                 Generator::MPFR => Generator::DELTA,
-                Generator::DELTA => Generator::MPFR,
+                Generator::DELTA => Generator::Auto,
             };
             println!("Use Generator: {:?}", new_gen);
-            State::calc(current.canvas, current.max, new_gen)
+            State::calc(current.canvas,
+                        current.max,
+                        new_gen,
+                        current.coloring,
+                        current.fractal,
+                        current.julia,
+                        current.orbits)
+        }
+        Action::SwitchColoring => {
+            let new_coloring = match current.coloring {
+                Coloring::Iterations => Coloring::Distance,
+                Coloring::Distance => Coloring::Interior(InteriorColoring::Period),
+                Coloring::Interior(InteriorColoring::Period) => {
+                    Coloring::Interior(InteriorColoring::Multiplier)
+                }
+                Coloring::Interior(InteriorColoring::Multiplier) => {
+                    Coloring::Interior(InteriorColoring::Distance)
+                }
+                Coloring::Interior(InteriorColoring::Distance) => Coloring::Iterations,
+            };
+            println!("Use Coloring: {:?}", new_coloring);
+            State::calc(current.canvas,
+                        current.max,
+                        current.generator,
+                        new_coloring,
+                        current.fractal,
+                        current.julia,
+                        current.orbits)
+        }
+        Action::SwitchFractal => {
+            let new_fractal = match current.fractal {
+                Fractal::Mandelbrot => Fractal::Multibrot3,
+                Fractal::Multibrot3 => Fractal::Multibrot4,
+                Fractal::Multibrot4 => Fractal::BurningShip,
+                Fractal::BurningShip => Fractal::Tricorn,
+                Fractal::Tricorn => Fractal::Celtic,
+                Fractal::Celtic => Fractal::Nebulabrot,
+                Fractal::Nebulabrot => Fractal::Newton,
+                Fractal::Newton => Fractal::Nova,
+                Fractal::Nova => Fractal::Mandelbrot,
+            };
+            println!("Use Fractal: {:?}", new_fractal);
+            State::calc(current.canvas,
+                        current.max,
+                        current.generator,
+                        current.coloring,
+                        new_fractal,
+                        current.julia,
+                        current.orbits)
+        }
+        Action::ToggleJulia(loc, win_size) => {
+            match current.julia.clone() {
+                Some(julia) => {
+                    println!("Back to the Mandelbrot set");
+                    State::calc(julia.mandelbrot,
+                                current.max,
+                                current.generator,
+                                current.coloring,
+                                current.fractal,
+                                None,
+                                current.orbits)
+                }
+                None => {
+                    let pixel = current.pixel_at(loc, win_size);
+                    let c = current.canvas.coordinates([pixel[0] as u32, pixel[1] as u32]);
+                    println!("Julia set of {}, {}", c[0], c[1]);
+
+                    let canvas = CanvasSize::new_from_center(current.canvas.pixel_width,
+                                                             current.canvas.pixel_height,
+                                                             [mpfr!(0.0), mpfr!(0.0)],
+                                                             mpfr!(0.75));
+                    let canvas = canvas.set_prec(canvas.required_prec());
+                    let julia = Julia {
+                        c: c,
+                        mandelbrot: current.canvas.clone(),
+                    };
+                    State::calc(canvas,
+                                current.max,
+                                current.generator,
+                                current.coloring,
+                                current.fractal,
+                                Some(julia),
+                                current.orbits)
+                }
+            }
+        }
+        Action::SnapToMinibrot(loc, win_size) => snap_to_minibrot(current, loc, win_size),
+        Action::ShowPeriod(_, _) if current.julia.is_some() ||
+                                    current.fractal != Fractal::Mandelbrot => current,
+        Action::ShowPeriod(loc, win_size) => {
+            match find_period(&current.region_at(loc, win_size), current.max) {
+                Some(period) => println!("Period: {}", period),
+                None => println!("No period up to {}", current.max),
+            }
+            current
+        }
+    }
+}
+
+/// Frames the minibrot nearest to the pixel under the cursor. Its period is
+/// found for the region around the cursor, or else guessed from the atom
+/// domain the pixel lies in.
+fn snap_to_minibrot(current: State, loc: [f64; 2], win_size: (u32, u32)) -> State {
+    if current.julia.is_some() || current.fractal != Fractal::Mandelbrot {
+        return current;
+    }
+
+    let pixel = current.pixel_at(loc, win_size);
+    let c = current.canvas.coordinates([pixel[0] as u32, pixel[1] as u32]);
+    let period = find_period(&current.region_at(loc, win_size), current.max)
+        .unwrap_or_else(|| atom_domain_period(c[0].clone(), c[1].clone(), current.max));
+
+    match zoom_to_minibrot(&current.canvas, &c, period) {
+        Some(canvas) => {
+            let center = canvas.center();
+            println!("Minibrot of period {} around {}, {}", period, center[0], center[1]);
+            let max = current.max;
+            current.recalc(canvas, max)
+        }
+        None => {
+            println!("No nucleus of period {} found", period);
+            current
         }
     }
 }
@@ -45,9 +45,10 @@ fn main() {
     let x = Mpfr::new2_from_str(prec, x_, 10).expect("Wrong literal");
     let y = Mpfr::new2_from_str(prec, y_, 10).expect("Wrong literal");
     let zoom = Mpfr::new2_from_str(prec, zoom_, 10).expect("Wrong literal");
-    println!("Initial precision: {}", (&x * &y + &zoom).get_prec());
 
-    let canvas = CanvasSize::new_from_center(400, 200, [x, y], zoom).set_prec(128);
+    let canvas = CanvasSize::new_from_center(400, 200, [x, y], zoom);
+    let canvas = canvas.set_prec(canvas.required_prec());
+    println!("Initial precision: {}", canvas.get_prec());
     let max = 1000u32;
 
     let mut driver2d = Driver2d::new(settings());
@@ -1,10 +1,44 @@
 use palette::{Hsv, Gradient, IntoColor, RgbHue};
-use std::ops::{Mul, Add, Neg, Sub};
 use rayon::prelude::*;
 use rust_mpfr::mpfr::*;
 use image;
 use num::complex::Complex64;
-use std::fmt::Display;
+
+mod autopilot;
+mod buddhabrot;
+mod floatexp;
+mod formula;
+mod interior;
+mod julia;
+mod multidouble;
+mod newton;
+mod nucleus;
+mod perturbation;
+mod real;
+mod subdivision;
+
+pub use self::autopilot::{MAX_AUTOPILOT_ITERATIONS, autopilot, interesting_pixel};
+pub use self::buddhabrot::{Sampler, calculate_buddhabrot, calculate_nebulabrot};
+pub use self::floatexp::{ComplexExp, FloatExp};
+pub use self::formula::{BurningShip, Celtic, Formula, Mandelbrot, Multibrot, Tricorn,
+                        calculate_all_formula, iterate_all_formula, iterate_formula};
+pub use self::interior::{Interior, calculate_all_delta_interior,
+                         calculate_all_delta_interior_cached, calculate_all_interior,
+                         iterate_with_interior};
+pub use self::julia::{calculate_all_julia, iterate_julia};
+pub use self::multidouble::{DoubleDouble, QuadDouble};
+pub use self::newton::{Convergence, Polynomial, calculate_all_newton, calculate_all_nova,
+                       iterate_newton};
+pub use self::nucleus::{atom_domain_period, find_misiurewicz, find_nucleus, find_period,
+                        minibrot_size, misiurewicz_canvas, zoom_to_minibrot};
+pub use self::real::Real;
+pub use self::perturbation::{BilinearApproximation, Cycle, OrbitCache, ReferenceOrbit,
+                              SeriesApproximation, calculate_all_delta,
+                              calculate_all_delta_cached, calculate_all_delta_distance,
+                              calculate_all_delta_distance_cached,
+                              calculate_all_delta_with_glitches, iterate_perturbed,
+                              perturbed_cycle};
+pub use self::subdivision::calculate_all_subdivided;
 
 #[derive(Clone)]
 pub struct CanvasSize {
@@ -39,22 +73,18 @@ impl CanvasSize {
     }
 
     pub fn set_prec(&self, prec: usize) -> CanvasSize {
-        let mut new_top = Mpfr::new2(prec);
-        let mut new_bottom = Mpfr::new2(prec);
-        let mut new_left = Mpfr::new2(prec);
-        let mut new_right = Mpfr::new2(prec);
-
-        new_top.set(&self.top);
-        new_bottom.set(&self.bottom);
-        new_left.set(&self.left);
-        new_right.set(&self.right);
-
         CanvasSize::new(self.pixel_width,
                         self.pixel_height,
-                        new_top,
-                        new_bottom,
-                        new_left,
-                        new_right)
+                        with_prec(&self.top, prec),
+                        with_prec(&self.bottom, prec),
+                        with_prec(&self.left, prec),
+                        with_prec(&self.right, prec))
+    }
+
+    /// Minimum precision in bits that still tells adjacent pixels apart, plus a
+    /// margin for the rounding errors that build up while iterating.
+    pub fn required_prec(&self) -> usize {
+        required_prec(self.pixel_width, &self.center(), &self.get_zoom())
     }
 
     pub fn new_from_center(pixel_width: u32,
@@ -74,6 +104,18 @@ impl CanvasSize {
         CanvasSize::new(pixel_width, pixel_height, top, bottom, left, right)
     }
 
+    /// Like `new_from_center`, but with the precision the zoom requires.
+    pub fn new_centered(pixel_width: u32,
+                        pixel_height: u32,
+                        center: [Mpfr; 2],
+                        zoom: Mpfr)
+                        -> CanvasSize {
+        let prec = required_prec(pixel_width, &center, &zoom);
+        let center = [with_prec(&center[0], prec), with_prec(&center[1], prec)];
+
+        CanvasSize::new_from_center(pixel_width, pixel_height, center, zoom).set_prec(prec)
+    }
+
     fn width(&self) -> Mpfr {
         &self.right - &self.left
     }
@@ -87,10 +129,22 @@ impl CanvasSize {
     }
 
     pub fn zoom(&self, zoom: Mpfr) -> CanvasSize {
-        CanvasSize::new_from_center(self.pixel_width,
-                                    self.pixel_height,
-                                    self.center(),
-                                    self.get_zoom() * zoom)
+        self.reframe(self.center(), self.get_zoom() * zoom)
+    }
+
+    /// Same pixel size at a new center and zoom, with the precision it requires.
+    fn reframe(&self, center: [Mpfr; 2], zoom: Mpfr) -> CanvasSize {
+        CanvasSize::new_centered(self.pixel_width, self.pixel_height, center, zoom)
+    }
+
+    /// Same pixel size, framing the minibrot at `nucleus` of the given size
+    /// estimate like the initial view frames the whole set.
+    pub fn frame_minibrot(&self, nucleus: &[Mpfr; 2], size: ComplexExp) -> CanvasSize {
+        let prec = nucleus[0].get_prec();
+        let center = [&nucleus[0] - size.re.to_mpfr(prec) / 2.0,
+                      &nucleus[1] - size.im.to_mpfr(prec) / 2.0];
+
+        self.reframe(center, 1.0 / size.norm().to_mpfr(prec))
     }
 
     pub fn get_zoom(&self) -> Mpfr {
@@ -107,7 +161,7 @@ impl CanvasSize {
     pub fn move_center_to_pixel(&self, coord: [f64; 2]) -> CanvasSize {
         let new_center = self.coordinates([coord[0] as u32, coord[1] as u32]);
 
-        self.move_center(new_center)
+        self.reframe(new_center, self.get_zoom())
     }
 
     pub fn coordinates(&self, pixel_coordinates: [u32; 2]) -> [Mpfr; 2] {
@@ -120,6 +174,11 @@ impl CanvasSize {
         [x_, y_]
     }
 
+    fn contains(&self, point: &[Mpfr; 2]) -> bool {
+        self.left <= point[0] && point[0] <= self.right && self.bottom <= point[1] &&
+        point[1] <= self.top
+    }
+
     fn coord_to_idx(&self, c: [u32; 2]) -> usize {
         assert!(c[0] < self.pixel_width);
         assert!(c[1] < self.pixel_height);
@@ -135,120 +194,462 @@ impl CanvasSize {
     fn pixel_count(&self) -> u32 {
         self.pixel_width * self.pixel_height
     }
+
+    fn pixel_spacing(&self) -> FloatExp {
+        FloatExp::from(&(self.width() / self.pixel_width as f64))
+    }
 }
 
-pub fn iterate<T>(x0: T, y0: T, max_iterations: u32) -> Option<u32>
-    where T: Add<Output=T> + for<'a> Add<&'a T, Output=T>
-           + Mul<Output=T> + for<'a> Mul<&'a T, Output=T>
-           + Neg + Sub<Output=T> + From<f64>
-           + Clone + PartialOrd + Display,
-          for <'a> &'a T: Mul<Output=T>
-{
-    let i = iterate_all::<T>(x0, y0, max_iterations).len() as u32;
+fn to_f64(x: &Mpfr) -> f64 {
+    x.into()
+}
 
-    if i != max_iterations {
-        Some(i)
-    } else {
-        None
+fn required_prec(pixel_width: u32, center: &[Mpfr; 2], zoom: &Mpfr) -> usize {
+    const MARGIN: i64 = 32;
+    const MIN_PREC: i64 = 53;
+
+    let width = 3.0 / zoom.clone();
+    let magnitude = [&center[0], &center[1], &width]
+        .iter()
+        .map(|x| FloatExp::from(*x).exponent())
+        .max()
+        .unwrap();
+    let spacing = FloatExp::from(&(width / pixel_width as f64)).exponent();
+
+    ::std::cmp::max(magnitude - spacing + 1 + MARGIN, MIN_PREC) as usize
+}
+
+fn with_prec(x: &Mpfr, prec: usize) -> Mpfr {
+    let mut y = Mpfr::new2(prec);
+    y.set(x);
+    y
+}
+
+/// Escape radius. Far larger than 2, so that the smooth iteration count has no
+/// visible steps.
+pub const BAILOUT: f64 = 256.0;
+const BAILOUT_SQR: f64 = BAILOUT * BAILOUT;
+
+/// Normalized iteration count of a point that escaped after `iterations` with
+/// the final `|z|²`. Lies in `(iterations, iterations + 1]`.
+pub fn smooth_iteration_count(iterations: u32, norm_sqr: f64) -> f64 {
+    smooth_iteration_count_for_degree(iterations, norm_sqr, 2)
+}
+
+/// Like `smooth_iteration_count`, for formulas where `|z|` grows like `|z|^degree`.
+pub fn smooth_iteration_count_for_degree(iterations: u32, norm_sqr: f64, degree: u32) -> f64 {
+    iterations as f64 + 1.0 - (norm_sqr.ln() / BAILOUT_SQR.ln()).ln() / (degree as f64).ln()
+}
+
+/// Outcome of iterating a single point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Iteration {
+    /// Escaped after the given number of iterations, with the final `|z|²`.
+    Escaped(u32, f64),
+    /// Caught in a cycle of the given period, so it is an interior point.
+    Periodic(u32),
+    /// Neither escaped nor found periodic within the iteration limit.
+    Bounded,
+}
+
+impl Iteration {
+    pub fn escaped(&self) -> Option<u32> {
+        match *self {
+            Iteration::Escaped(i, _) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// The smooth iteration count, `None` for points that did not escape.
+    pub fn smooth(&self) -> Option<f64> {
+        match *self {
+            Iteration::Escaped(i, norm_sqr) => Some(smooth_iteration_count(i, norm_sqr)),
+            _ => None,
+        }
     }
 }
 
-pub fn iterate_all<T>(x0: T, y0: T, max_iterations: u32) -> Vec<(T, T)>
-    where T: Add<Output=T> + for<'a> Add<&'a T, Output=T>
-           + Mul<Output=T> + for<'a> Mul<&'a T, Output=T>
-           + Neg + Sub<Output=T> + From<f64>
-           + Clone + PartialOrd + Display,
-          for <'a> &'a T: Mul<Output=T>
-{
+/// Iterates `z² + c` from `z = 0`, handing every new `z` to `visit`. Returns the
+/// final `z` along with the outcome.
+fn iterate_with<T: Real, F: FnMut(&T, &T)>(x0: &T,
+                                           y0: &T,
+                                           max_iterations: u32,
+                                           visit: F)
+                                           -> (Iteration, T, T) {
+    iterate_from(&Mandelbrot, T::from_f64(0.0), T::from_f64(0.0), x0, y0, max_iterations, visit)
+}
+
+/// Iterates `formula` from the given `z`, see `iterate_with`.
+///
+/// Cycles are detected with Brent's method: the orbit is compared against a
+/// saved point, which is replaced after windows of doubling length. Two points
+/// closer than the precision of `z` and `c` resolves (minus a few bits) count
+/// as equal.
+fn iterate_from<T: Real, Fo: Formula, F: FnMut(&T, &T)>(formula: &Fo,
+                                                        mut x: T,
+                                                        mut y: T,
+                                                        x0: &T,
+                                                        y0: &T,
+                                                        max_iterations: u32,
+                                                        mut visit: F)
+                                                        -> (Iteration, T, T) {
+    let tolerance = 8 - ::std::cmp::max(x.prec(), x0.prec()) as i64;
+
     let mut i = 0;
-    let mut x = T::from(0.0f64);
-    let mut y = T::from(0.0f64);
-    let mut v = Vec::new();
+    let mut check = (x.clone(), y.clone());
+    let mut window = 1;
+    let mut steps = 0;
 
-    while &x * &x + &y * &y < T::from(4.0f64) && i < max_iterations {
-        let xtemp = &x * &x - &y * &y + &x0;
-        let ytemp = T::from(2.0f64) * &x * &y + &y0;
+    while T::norm_sqr(&x, &y).lt_f64(BAILOUT_SQR) && i < max_iterations {
+        let (xtemp, ytemp) = formula.step(&x, &y, x0, y0);
 
-        v.push((xtemp.clone(), ytemp.clone()));
+        x = xtemp;
+        y = ytemp;
         i += 1;
+        steps += 1;
+        visit(&x, &y);
 
-        if x == xtemp && y == ytemp {
-            for _ in 0..(max_iterations - i) {
-                v.push((x.clone(), y.clone()));
-            }
-            i = max_iterations;
+        if ::std::cmp::max(x.minus(&check.0).exponent(), y.minus(&check.1).exponent()) < tolerance {
+            return (Iteration::Periodic(steps), x, y);
+        }
+        if steps == window {
+            window *= 2;
+            steps = 0;
+            check = (x.clone(), y.clone());
         }
+    }
 
-        x = xtemp;
-        y = ytemp;
+    if i != max_iterations {
+        let norm_sqr = T::norm_sqr(&x, &y).to_f64();
+        (Iteration::Escaped(i, norm_sqr), x, y)
+    } else {
+        (Iteration::Bounded, x, y)
     }
+}
 
-    v
+/// Whether `c` lies inside the main cardioid or the period-2 bulb, where the
+/// orbit is known to end up in a cycle of period 1 or 2 respectively.
+pub fn in_cardioid_or_period2_bulb<T: Real>(x: &T, y: &T) -> bool {
+    let y2 = y.square();
+
+    let xq = x.minus(&T::from_f64(0.25));
+    let q = xq.square() + y2.clone();
+    if (q.times(&q.plus(&xq)) - y2.times(&T::from_f64(0.25))).lt_f64(0.0) {
+        return true;
+    }
+
+    (x.plus(&T::from_f64(1.0)).square() + y2).lt_f64(0.0625)
+}
+
+/// Iterates a single point, reporting the period of interior points when found.
+pub fn iterate_with_period<T: Real>(x0: T, y0: T, max_iterations: u32) -> Iteration {
+    iterate_formula(&Mandelbrot, x0, y0, max_iterations)
+}
+
+pub fn iterate<T: Real>(x0: T, y0: T, max_iterations: u32) -> Option<u32> {
+    iterate_with_period(x0, y0, max_iterations).escaped()
+}
+
+/// Exterior distance estimate `|z| ln|z|² / |dz/dc|` from the final `z` and
+/// `dz/dc` of an escaped point. The distance to the set lies between a quarter
+/// of the estimate and the estimate itself.
+pub fn distance_estimate(z: Complex64, dz: ComplexExp) -> FloatExp {
+    FloatExp::from(z.norm() * z.norm_sqr().ln()) / dz.norm()
 }
 
-pub fn delta(d: Complex64, x_n: Complex64, input: [Complex64; 4]) -> (Complex64, [Complex64; 3]) {
+/// Iterates a single point together with `dz/dc` and returns its distance
+/// estimate, or zero if it did not escape.
+///
+/// The derivative grows like the zoom, so it is kept as a `ComplexExp`.
+pub fn iterate_with_distance<T: Real>(x0: T, y0: T, max_iterations: u32) -> (Iteration, FloatExp) {
+    if in_cardioid_or_period2_bulb(&x0, &y0) {
+        return (iterate_with_period(x0, y0, max_iterations), FloatExp::from(0.0));
+    }
+
+    let one = ComplexExp::from(Complex64::new(1.0, 0.0));
+    let mut z = Complex64::new(0.0, 0.0);
+    let mut dz = ComplexExp::from(z);
+
+    let result = iterate_with(&x0, &y0, max_iterations, |x, y| {
+            dz = ComplexExp::from(z * 2.0) * dz + one;
+            z = Complex64::new(x.to_f64(), y.to_f64());
+        })
+        .0;
+
+    match result {
+        Iteration::Escaped(..) => (result, distance_estimate(z, dz)),
+        _ => (result, FloatExp::from(0.0)),
+    }
+}
+
+/// The orbit of a point under `z² + c`, see `iterate_all_formula`.
+pub fn iterate_all<T: Real>(x0: T, y0: T, max_iterations: u32) -> Vec<(T, T)> {
+    iterate_all_formula(&Mandelbrot, x0, y0, max_iterations)
+}
+
+pub fn delta(d: Complex64, x_n: Complex64, input: [Complex64; 3]) -> (Complex64, [Complex64; 3]) {
     let a_n = input[0];
     let b_n = input[1];
     let c_n = input[2];
 
     let a_n1 = 2f64 * a_n * x_n + 1f64;
     let b_n1 = 2f64 * b_n * x_n + a_n * a_n;
-    let c_n1 = 2f64 * c_n * x_n + a_n * b_n;
+    let c_n1 = 2f64 * c_n * x_n + 2f64 * a_n * b_n;
     let x_n1 = a_n1 * d + b_n1 * d * d + c_n1 * d * d * d;
 
     (x_n1, [a_n1, b_n1, c_n1])
 }
 
-pub fn calculate_all_mpfr(canvas_size: CanvasSize, max_iterations: u32) -> Vec<u32> {
-    let mut v: Vec<u32> = Vec::new();
-    (0..canvas_size.pixel_count())
-        .into_par_iter()
-        .weight_max()
-        .map(|i| canvas_size.coordinates(canvas_size.idx_to_coord(i as usize)))
-        .map(|c| {
-            iterate::<Mpfr>((c[0].clone()), (c[1].clone()), max_iterations)
-                .unwrap_or(max_iterations)
-        })
-        .collect_into(&mut v);
-    v
+/// Maps pixels to points in `T`, with the corners converted from MPFR once.
+struct Pixels<T> {
+    left: T,
+    top: T,
+    width: T,
+    height: T,
+    pixel_width: f64,
+    pixel_height: f64,
 }
 
-pub fn calculate_all_delta(canvas_size: CanvasSize, max_iterations: u32) -> Vec<u32> {
-    let mut v: Vec<u32> = Vec::new();
+impl<T: Real> Pixels<T> {
+    fn new(canvas_size: &CanvasSize) -> Pixels<T> {
+        Pixels {
+            left: T::from_mpfr(&canvas_size.left),
+            top: T::from_mpfr(&canvas_size.top),
+            width: T::from_mpfr(&canvas_size.width()),
+            height: T::from_mpfr(&canvas_size.height()),
+            pixel_width: canvas_size.pixel_width as f64,
+            pixel_height: canvas_size.pixel_height as f64,
+        }
+    }
+
+    fn point(&self, p: [u32; 2]) -> (T, T) {
+        let x = self.left.plus(&self.width.times(&T::from_f64(p[0] as f64 / self.pixel_width)));
+        let y = self.top.minus(&self.height.times(&T::from_f64(p[1] as f64 / self.pixel_height)));
+        (x, y)
+    }
+}
+
+/// Evaluates `f` at every pixel in `T`.
+fn render<T, R, F>(canvas_size: &CanvasSize, f: F) -> Vec<R>
+    where T: Real,
+          R: Send,
+          F: Fn(T, T) -> R + Sync
+{
+    let pixels = Pixels::new(canvas_size);
+
+    let mut v: Vec<R> = Vec::new();
     (0..canvas_size.pixel_count())
         .into_par_iter()
         .weight_max()
-        .map(|i| canvas_size.coordinates(canvas_size.idx_to_coord(i as usize)))
-        .map(|c| {
-            iterate::<Mpfr>((c[0].clone()), (c[1].clone()), max_iterations)
-                .unwrap_or(max_iterations)
-        })
+        .map(|i| pixels.point(canvas_size.idx_to_coord(i as usize)))
+        .map(|(x, y)| f(x, y))
         .collect_into(&mut v);
     v
 }
 
-fn color_from_iteration(iterations: u32, max_iterations: u32) -> [u8; 3] {
-    const N_COLORS: u32 = 256u32;
+/// Renders the canvas in `T`. Every pixel holds its smooth iteration count,
+/// `None` if it did not escape.
+pub fn calculate_all<T: Real>(canvas_size: CanvasSize, max_iterations: u32) -> Vec<Option<f64>> {
+    render(&canvas_size, |x: T, y| iterate_with_period(x, y, max_iterations).smooth())
+}
+
+/// Renders the distance estimate of every pixel in `T`, in units of the pixel
+/// spacing. Pixels that do not escape get zero.
+pub fn calculate_all_distance<T: Real>(canvas_size: CanvasSize, max_iterations: u32) -> Vec<f64> {
+    let spacing = canvas_size.pixel_spacing();
+
+    render(&canvas_size, |x: T, y| {
+        (iterate_with_distance(x, y, max_iterations).1 / spacing).to_f64()
+    })
+}
+
+pub fn calculate_all_mpfr(canvas_size: CanvasSize, max_iterations: u32) -> Vec<Option<f64>> {
+    calculate_all::<Mpfr>(canvas_size, max_iterations)
+}
+
+/// Renders the canvas in plain `f64`, only exact while `required_prec()` is at most 53.
+pub fn calculate_all_f64(canvas_size: CanvasSize, max_iterations: u32) -> Vec<Option<f64>> {
+    calculate_all::<f64>(canvas_size, max_iterations)
+}
+
+/// Renders the canvas in `DoubleDouble`, exact while `required_prec()` is at most 106.
+pub fn calculate_all_double_double(canvas_size: CanvasSize, max_iterations: u32) -> Vec<Option<f64>> {
+    calculate_all::<DoubleDouble>(canvas_size, max_iterations)
+}
+
+/// Renders the canvas in `QuadDouble`, exact while `required_prec()` is at most 212.
+pub fn calculate_all_quad_double(canvas_size: CanvasSize, max_iterations: u32) -> Vec<Option<f64>> {
+    calculate_all::<QuadDouble>(canvas_size, max_iterations)
+}
+
+/// Colors an escaped pixel by its smooth iteration count and paints pixels
+/// that did not escape (`None`) black.
+fn color_from_iteration(iterations: Option<f64>) -> [u8; 3] {
+    const N_COLORS: f64 = 256f64;
     const BLACK: [u8; 3] = [0u8, 0u8, 0u8];
 
     let grad = Gradient::new(vec![Hsv::new(RgbHue::from(0f32), 1.0, 1.0),
                                   Hsv::new(RgbHue::from(180f32), 1.0, 1.0),
                                   Hsv::new(RgbHue::from(360f32), 1.0, 1.0)]);
 
-    if iterations == max_iterations {
-        BLACK
-    } else {
-        grad.get((iterations % N_COLORS) as f32 / N_COLORS as f32).into_rgb().to_pixel()
+    match iterations {
+        Some(i) => grad.get(((i % N_COLORS) / N_COLORS) as f32).into_rgb().to_pixel(),
+        None => BLACK,
     }
 }
 
-pub fn make_image(data: Vec<u32>, canvas_size: CanvasSize, max_iterations: u32) -> image::RgbImage {
+pub fn make_image(data: Vec<Option<f64>>, canvas_size: CanvasSize) -> image::RgbImage {
     image::RgbImage::from_fn(canvas_size.pixel_width, canvas_size.pixel_height, |x, y| {
         let i = data[canvas_size.coord_to_idx([x, y])];
-        image::Rgb(color_from_iteration(i, max_iterations))
+        image::Rgb(color_from_iteration(i))
+    })
+}
+
+/// The hue of the `k`th of a sequence of colors. Steps of the golden angle keep
+/// neighbouring indices apart.
+fn golden_hue(k: usize) -> RgbHue {
+    RgbHue::from((k as f32 * 137.5) % 360.0)
+}
+
+/// How `make_interior_image` paints the pixels that did not escape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InteriorColoring {
+    /// Hue by the period of the attracting cycle.
+    Period,
+    /// Hue by the angle of the multiplier, brightness by its magnitude.
+    Multiplier,
+    /// Grey by the interior distance estimate in pixels.
+    Distance,
+}
+
+fn color_from_interior(interior: &Interior, spacing: FloatExp, coloring: InteriorColoring) -> [u8; 3] {
+    match coloring {
+        InteriorColoring::Period => {
+            Hsv::new(golden_hue(interior.period as usize), 0.7, 0.8).into_rgb().to_pixel()
+        }
+        InteriorColoring::Multiplier => {
+            let hue = interior.multiplier.arg().to_degrees() as f32;
+            let value = 0.2 + 0.8 * interior.multiplier.norm() as f32;
+            Hsv::new(RgbHue::from(hue), 0.8, value).into_rgb().to_pixel()
+        }
+        InteriorColoring::Distance => {
+            let v = (255.0 * ((interior.distance / spacing).to_f64() / 16.0).tanh()) as u8;
+            [v, v, v]
+        }
+    }
+}
+
+/// Like `make_image`, but paints the interior pixels by their cycle.
+pub fn make_interior_image(data: Vec<Option<f64>>,
+                           interior: Vec<Option<Interior>>,
+                           canvas_size: CanvasSize,
+                           coloring: InteriorColoring)
+                           -> image::RgbImage {
+    let spacing = canvas_size.pixel_spacing();
+
+    image::RgbImage::from_fn(canvas_size.pixel_width, canvas_size.pixel_height, |x, y| {
+        let idx = canvas_size.coord_to_idx([x, y]);
+        match interior[idx] {
+            Some(ref i) => image::Rgb(color_from_interior(i, spacing, coloring)),
+            None => image::Rgb(color_from_iteration(data[idx])),
+        }
     })
 }
 
+/// Shades pixels by their distance estimate in pixels, from black on the
+/// boundary to white about two pixels away from it.
+pub fn make_distance_image(distances: Vec<f64>, canvas_size: CanvasSize) -> image::RgbImage {
+    image::RgbImage::from_fn(canvas_size.pixel_width, canvas_size.pixel_height, |x, y| {
+        let d = distances[canvas_size.coord_to_idx([x, y])];
+        let v = (255.0 * d.tanh()) as u8;
+        image::Rgb([v, v, v])
+    })
+}
+
+/// Paints pixels by the root they converged to, darker the longer they took.
+/// Pixels that settled elsewhere are grey, those that did not settle black.
+pub fn make_root_image(data: Vec<Convergence>, canvas_size: CanvasSize) -> image::RgbImage {
+    image::RgbImage::from_fn(canvas_size.pixel_width, canvas_size.pixel_height, |x, y| {
+        let rgb = match data[canvas_size.coord_to_idx([x, y])] {
+            Convergence::Converged(root, i) => {
+                let value = 0.2 + 0.8 * (-i as f32 / 16.0).exp();
+                match root {
+                    Some(k) => Hsv::new(golden_hue(k), 0.8, value).into_rgb().to_pixel(),
+                    None => Hsv::new(RgbHue::from(0.0), 0.0, value).into_rgb().to_pixel(),
+                }
+            }
+            Convergence::Unconverged => [0, 0, 0],
+        };
+        image::Rgb(rgb)
+    })
+}
+
+/// Scale that maps a density channel into `[0, 1]`. The brightest pixels are
+/// few and far above the rest, so they are clipped rather than set to white.
+fn white_point(data: &[f64]) -> f64 {
+    let mut sorted: Vec<f64> = data.iter().cloned().filter(|&d| d > 0.0).collect();
+    if sorted.is_empty() {
+        return 1.0;
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    sorted[(sorted.len() - 1) * 999 / 1000]
+}
+
+/// Tone maps densities such as the Nebulabrot, each channel on its own.
+pub fn make_density_image(data: Vec<[f64; 3]>, canvas_size: CanvasSize) -> image::RgbImage {
+    let mut white = [0.0; 3];
+    for (channel, w) in white.iter_mut().enumerate() {
+        *w = white_point(&data.iter().map(|d| d[channel]).collect::<Vec<_>>());
+    }
+
+    image::RgbImage::from_fn(canvas_size.pixel_width, canvas_size.pixel_height, |x, y| {
+        let d = data[canvas_size.coord_to_idx([x, y])];
+        let mut rgb = [0u8; 3];
+        for channel in 0..3 {
+            rgb[channel] = (255.0 * (d[channel] / white[channel]).min(1.0).sqrt()) as u8;
+        }
+        image::Rgb(rgb)
+    })
+}
+
+/// Tone maps a single density such as the Buddhabrot in grey.
+pub fn make_buddhabrot_image(data: Vec<f64>, canvas_size: CanvasSize) -> image::RgbImage {
+    make_density_image(data.into_iter().map(|d| [d, d, d]).collect(), canvas_size)
+}
+
+/// Whether two pixels both stayed bounded or escaped with nearly the same smooth count.
+#[cfg(test)]
+fn same_count(a: Option<f64>, b: Option<f64>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => (a - b).abs() <= 1e-6,
+        (a, b) => a == b,
+    }
+}
+
+/// Counts the pixels on which two renders of the same canvas disagree.
+#[cfg(test)]
+fn mismatches(a: &[Option<f64>], b: &[Option<f64>]) -> usize {
+    a.iter().zip(b.iter()).filter(|&(&x, &y)| !same_count(x, y)).count()
+}
+
+/// Asserts that `v` agrees with the MPFR render of the same canvas up to
+/// rounding, which may tip at most 1% of the pixels into another band.
+#[cfg(test)]
+fn assert_matches_mpfr(v: &[Option<f64>], mpfr: &[Option<f64>]) {
+    assert_eq!(v.len(), mpfr.len());
+    assert!(mismatches(v, mpfr) * 100 <= mpfr.len(),
+            "{} of {} pixels differ from MPFR",
+            mismatches(v, mpfr),
+            mpfr.len());
+}
+
+/// A 60x40 canvas at (-0.75, 0.1), with filaments, bulbs and glitches under
+/// perturbation.
+#[cfg(test)]
+fn test_canvas() -> CanvasSize {
+    CanvasSize::new_from_center(60, 40, [mpfr!(-0.75), mpfr!(0.1)], mpfr!(20.0))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -328,6 +729,49 @@ mod tests {
                    [mpfr!(0.0), mpfr!(-1.0)]);
     }
 
+    #[test]
+    fn test_f64_matches_mpfr() {
+        let c = CanvasSize::new_from_center(60, 40, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+
+        let f64 = calculate_all_f64(c.clone(), 200);
+        let mpfr = calculate_all_mpfr(c, 200);
+
+        assert_eq!(mismatches(&f64, &mpfr), 0);
+    }
+
+    #[test]
+    fn test_multidouble_matches_mpfr() {
+        let prec = 200;
+        let center = [Mpfr::new2_from_str(prec, "0.0", 10).unwrap(),
+                      Mpfr::new2_from_str(prec, "1.0", 10).unwrap()];
+        let zoom = Mpfr::new2_from_str(prec, "1e18", 10).unwrap();
+        let c = CanvasSize::new_from_center(30, 20, center, zoom);
+        let mpfr = calculate_all_mpfr(c.clone(), 2000);
+
+        assert!(mpfr.iter().any(|&i| i != mpfr[0]));
+        assert_matches_mpfr(&calculate_all_double_double(c.clone(), 2000), &mpfr);
+        assert_matches_mpfr(&calculate_all_quad_double(c, 2000), &mpfr);
+    }
+
+    #[test]
+    fn test_required_prec() {
+        let c = CanvasSize::new_from_center(900, 600, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+
+        assert_eq!(c.required_prec(), 53);
+        assert_eq!(c.zoom(mpfr!(1e30)).required_prec(), 140);
+    }
+
+    #[test]
+    fn test_zoom_adjusts_prec() {
+        let c = CanvasSize::new_from_center(900, 600, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+        let zoomed = c.move_center_to_pixel([300.0, 200.0]).zoom(mpfr!(1e40));
+
+        assert_eq!(zoomed.get_prec(), zoomed.required_prec());
+        assert!(zoomed.coordinates([0, 0])[0] < zoomed.coordinates([1, 0])[0]);
+        assert!(zoomed.coordinates([0, 0])[1] > zoomed.coordinates([0, 1])[1]);
+        assert_eq!(zoomed.zoom(mpfr!(1e-40)).get_prec(), 53);
+    }
+
     #[test]
     fn test_pixel_count() {
         let c = CanvasSize::new_from_center(2, 3, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
@@ -345,6 +789,118 @@ mod tests {
         assert_eq!(v.len(), 10);
     }
 
+    #[test]
+    fn test_iterate_with_period() {
+        assert_eq!(iterate_with_period::<f64>(0.0, 0.0, 1000), Iteration::Periodic(1));
+        assert_eq!(iterate_with_period::<f64>(-0.5, 0.0, 1000), Iteration::Periodic(1));
+        assert_eq!(iterate_with_period::<f64>(-1.0, 0.0, 1000), Iteration::Periodic(2));
+        assert_eq!(iterate_with_period::<f64>(-0.1225, 0.7449, 1000), Iteration::Periodic(3));
+        assert_eq!(iterate_with_period::<f64>(-1.7549, 0.0, 1000), Iteration::Periodic(3));
+        assert_eq!(iterate_with_period::<f64>(0.3, 0.0, 1000).escaped(), Some(15));
+        assert_eq!(iterate_with_period::<f64>(0.26, 0.0, 1000).escaped(), Some(32));
+        assert_eq!(iterate_with_period::<f64>(0.25, 0.0, 1000), Iteration::Bounded);
+    }
+
+    #[test]
+    fn test_smooth_iteration_count() {
+        // Escaping with |z| = BAILOUT or |z| = BAILOUT² bounds the fractional part.
+        assert_eq!(smooth_iteration_count(10, BAILOUT_SQR), 11.0);
+        assert!((smooth_iteration_count(10, BAILOUT_SQR * BAILOUT_SQR) - 10.0).abs() < 1e-12);
+
+        // A point escaping one iteration later lands at the same smooth count
+        // when its |z| is the square of the earlier one.
+        let s = smooth_iteration_count(10, 1e6);
+        assert!((smooth_iteration_count(11, 1e12) - s).abs() < 1e-12);
+        assert!(s > 10.0 && s < 11.0);
+
+        assert_eq!(Iteration::Bounded.smooth(), None);
+        assert_eq!(Iteration::Periodic(3).smooth(), None);
+    }
+
+    #[test]
+    fn test_smooth_count_is_continuous() {
+        // Neighbouring points on the real axis that escape after a different
+        // number of iterations still get nearly the same smooth count.
+        let counts: Vec<f64> = (0..1000)
+            .filter_map(|i| iterate_with_period::<f64>(0.3 + i as f64 * 1e-5, 0.0, 1000).smooth())
+            .collect();
+
+        assert_eq!(counts.len(), 1000);
+        assert!(counts[0].floor() != counts[999].floor());
+        assert!(counts.windows(2).all(|w| (w[0] - w[1]).abs() < 0.01));
+    }
+
+    #[test]
+    fn test_color_from_iteration() {
+        // Escaped pixels get a color even when their smooth count passes the limit.
+        assert_eq!(color_from_iteration(None), [0, 0, 0]);
+        assert!(color_from_iteration(Some(1000.5)) != [0, 0, 0]);
+        assert!(color_from_iteration(Some(1001.0)) != [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_distance_estimate() {
+        // The closest point of the set to these is its tip at -2.
+        for &(x, distance) in &[(-2.1, 0.1), (-2.5, 0.5), (-3.0, 1.0)] {
+            let (result, estimate) = iterate_with_distance::<f64>(x, 0.0, 1000);
+            let estimate = estimate.to_f64();
+
+            assert!(result.escaped().is_some());
+            assert!(estimate / 4.0 <= distance && distance <= estimate);
+        }
+
+        let (result, estimate) = iterate_with_distance::<f64>(-0.1225, 0.7449, 1000);
+        assert_eq!(result, Iteration::Periodic(3));
+        assert_eq!(estimate.to_f64(), 0.0);
+    }
+
+    #[test]
+    fn test_distance_f64_matches_mpfr() {
+        let c = test_canvas();
+        let f64 = calculate_all_distance::<f64>(c.clone(), 500);
+        let mpfr = calculate_all_distance::<Mpfr>(c, 500);
+
+        assert!(f64.contains(&0.0));
+        assert!(f64.iter().any(|&d| d > 1.0));
+        assert!(f64.iter().zip(mpfr.iter()).all(|(a, b)| (a - b).abs() <= 1e-6 * a.abs()));
+    }
+
+    #[test]
+    fn test_in_cardioid_or_period2_bulb() {
+        assert!(in_cardioid_or_period2_bulb(&-0.5f64, &0.0));
+        assert!(in_cardioid_or_period2_bulb(&0.0f64, &0.0));
+        assert!(in_cardioid_or_period2_bulb(&0.2f64, &0.5));
+        assert!(in_cardioid_or_period2_bulb(&-1.0f64, &0.0));
+        assert!(in_cardioid_or_period2_bulb(&-1.1f64, &0.2));
+        assert!(in_cardioid_or_period2_bulb(&mpfr!(-0.5), &mpfr!(0.3)));
+
+        assert!(!in_cardioid_or_period2_bulb(&0.3f64, &0.0));
+        assert!(!in_cardioid_or_period2_bulb(&0.25f64, &0.0));
+        assert!(!in_cardioid_or_period2_bulb(&-0.75f64, &0.1));
+        assert!(!in_cardioid_or_period2_bulb(&-1.25f64, &0.0));
+        assert!(!in_cardioid_or_period2_bulb(&-0.1225f64, &0.7449));
+        assert!(!in_cardioid_or_period2_bulb(&mpfr!(-2.0), &mpfr!(0.0)));
+    }
+
+    #[test]
+    fn test_iterate_with_period_mpfr() {
+        let x = Mpfr::new2_from_str(256, "-0.1225", 10).unwrap();
+        let y = Mpfr::new2_from_str(256, "0.7449", 10).unwrap();
+
+        assert_eq!(iterate_with_period::<Mpfr>(x, y, 10000), Iteration::Periodic(3));
+        assert_eq!(iterate_with_period::<Mpfr>(mpfr!(-1.0), mpfr!(0.0), 1000),
+                   Iteration::Periodic(2));
+    }
+
+    #[test]
+    fn test_iterate_all_continues_cycle() {
+        let v = iterate_all::<f64>(-1.0, 0.0, 10);
+
+        assert_eq!(v.len(), 10);
+        assert_eq!(v[8], (-1.0, 0.0));
+        assert_eq!(v[9], (0.0, 0.0));
+    }
+
     #[test]
     fn test_iterate_all_prec() {
         let prec = 128;
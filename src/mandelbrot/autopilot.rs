@@ -0,0 +1,166 @@
+use rust_mpfr::mpfr::*;
+use super::CanvasSize;
+
+/// Half the side of the square the local variance is taken over.
+const WINDOW: u32 = 2;
+/// Zoom factor of every step, the same as a click in the app.
+const ZOOM_STEP: f64 = 8.0;
+/// The iteration limit is never raised beyond this.
+pub const MAX_AUTOPILOT_ITERATIONS: u32 = 1 << 26;
+
+/// Picks the pixel to zoom into from the smooth iteration counts of a canvas:
+/// among the tenth of escaped pixels that took longest, so are closest to the
+/// boundary, the one whose surroundings vary most. `None` if the canvas is
+/// featureless.
+pub fn interesting_pixel(data: &[Option<f64>], canvas_size: &CanvasSize) -> Option<[u32; 2]> {
+    if canvas_size.pixel_width <= 2 * WINDOW || canvas_size.pixel_height <= 2 * WINDOW {
+        return None;
+    }
+
+    let mut escaped: Vec<f64> = data.iter().filter_map(|&i| i).collect();
+    if escaped.is_empty() {
+        return None;
+    }
+    escaped.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let threshold = escaped[escaped.len() * 9 / 10];
+
+    let mut best: Option<(f64, [u32; 2])> = None;
+    for y in WINDOW..canvas_size.pixel_height - WINDOW {
+        for x in WINDOW..canvas_size.pixel_width - WINDOW {
+            match data[canvas_size.coord_to_idx([x, y])] {
+                Some(i) if i >= threshold => {}
+                _ => continue,
+            }
+
+            let (mut n, mut sum, mut sum_sqr) = (0.0, 0.0, 0.0);
+            for v in y - WINDOW..y + WINDOW + 1 {
+                for u in x - WINDOW..x + WINDOW + 1 {
+                    if let Some(i) = data[canvas_size.coord_to_idx([u, v])] {
+                        n += 1.0;
+                        sum += i;
+                        sum_sqr += i * i;
+                    }
+                }
+            }
+            let variance = sum_sqr / n - (sum / n) * (sum / n);
+
+            if variance > best.map_or(0.0, |b| b.0) {
+                best = Some((variance, [x, y]));
+            }
+        }
+    }
+
+    best.map(|(_, pixel)| pixel)
+}
+
+/// Explores on its own from `canvas_size` down to `target_zoom`: renders every
+/// view with `render`, then zooms into its `interesting_pixel`. The iteration
+/// limit doubles whenever more than a few escaped pixels come close to it, up
+/// to `MAX_AUTOPILOT_ITERATIONS`.
+///
+/// Every visited view is handed to `log` along with its iteration limit.
+/// Stops early at a featureless view or once the limit would have to grow past
+/// its bound, and returns the last view.
+pub fn autopilot<R, L>(canvas_size: CanvasSize,
+                       max_iterations: u32,
+                       target_zoom: &Mpfr,
+                       render: R,
+                       mut log: L)
+                       -> CanvasSize
+    where R: Fn(CanvasSize, u32) -> Vec<Option<f64>>,
+          L: FnMut(&CanvasSize, u32)
+{
+    let mut canvas = canvas_size;
+    let mut max = max_iterations;
+
+    while canvas.get_zoom() < *target_zoom {
+        let data = render(canvas.clone(), max);
+        let pixel = match interesting_pixel(&data, &canvas) {
+            Some(pixel) => pixel,
+            None => break,
+        };
+
+        let escaped = data.iter().filter(|i| i.is_some()).count();
+        let late = data.iter().filter_map(|&i| i).filter(|&i| i > max as f64 / 2.0).count();
+        if late * 100 > escaped {
+            if max >= MAX_AUTOPILOT_ITERATIONS {
+                break;
+            }
+            max = max.saturating_mul(2).min(MAX_AUTOPILOT_ITERATIONS);
+        }
+
+        canvas = canvas.move_center_to_pixel([pixel[0] as f64, pixel[1] as f64])
+            .zoom(Mpfr::from(ZOOM_STEP));
+        log(&canvas, max);
+    }
+
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    #[test]
+    fn test_interesting_pixel() {
+        let c = CanvasSize::new_from_center(10, 10, [mpfr!(0.0), mpfr!(0.0)], mpfr!(1.0));
+        let mut data = vec![Some(5.0); 100];
+
+        assert_eq!(interesting_pixel(&data, &c), None);
+        assert_eq!(interesting_pixel(&vec![None; 100], &c), None);
+
+        data[c.coord_to_idx([3, 3])] = Some(50.0);
+        assert_eq!(interesting_pixel(&data, &c), Some([2, 2]));
+
+        // The noisy left half escapes early, the right half is closer to the set.
+        for y in 0..10 {
+            for x in 0..10 {
+                data[c.coord_to_idx([x, y])] = Some(if x < 5 {
+                    [1.0, 9.0][((x + y) % 2) as usize]
+                } else {
+                    40.0 + ((x + y) % 2) as f64
+                });
+            }
+        }
+        data[c.coord_to_idx([8, 8])] = None;
+        assert!(interesting_pixel(&data, &c).unwrap()[0] >= 5);
+    }
+
+    #[test]
+    fn test_autopilot() {
+        let c = CanvasSize::new_from_center(60, 40, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+        let mut visited = Vec::new();
+        let last = autopilot(c,
+                             100,
+                             &mpfr!(1e5),
+                             calculate_all_f64,
+                             |c, max| visited.push((c.get_zoom().to_f64(), max)));
+
+        assert_eq!(visited.len(), 6);
+        for (i, &(zoom, _)) in visited.iter().enumerate() {
+            assert!((zoom / 8f64.powi(i as i32 + 1) - 1.0).abs() < 1e-6);
+        }
+        assert!(visited.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        let max = visited[5].1;
+        assert!(interesting_pixel(&calculate_all_f64(last.clone(), max), &last).is_some());
+    }
+
+    #[test]
+    fn test_autopilot_bounds_iterations() {
+        let c = CanvasSize::new_from_center(20, 20, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+        // Every pixel escapes late, so the limit keeps doubling.
+        let late = |_, max: u32| {
+            (0..400).map(|i| Some(max as f64 * 0.9 + (i % 7) as f64)).collect()
+        };
+        let mut visited = Vec::new();
+        autopilot(c,
+                  MAX_AUTOPILOT_ITERATIONS / 4 + 1,
+                  &mpfr!(1e100),
+                  late,
+                  |_, max| visited.push(max));
+
+        assert_eq!(visited, vec![MAX_AUTOPILOT_ITERATIONS / 2 + 2, MAX_AUTOPILOT_ITERATIONS]);
+    }
+}
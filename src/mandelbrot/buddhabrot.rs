@@ -0,0 +1,268 @@
+use rayon::prelude::*;
+use super::{CanvasSize, Iteration, in_cardioid_or_period2_bulb, iterate_with, to_f64};
+
+/// How the values of `c` are picked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampler {
+    /// Uniformly over the square `[-2, 2]²`, which holds the whole set.
+    Uniform,
+    /// Metropolis-Hastings, spending the samples on the `c` whose orbits cross
+    /// the canvas. Needed once the canvas is zoomed in.
+    Metropolis,
+}
+
+/// Samples are split into this many independent chains.
+const CHUNKS: u32 = 64;
+/// Chance that Metropolis-Hastings proposes a fresh uniform `c` instead of a
+/// small mutation, so that the chains do not get stuck.
+const LARGE_STEP: f64 = 0.2;
+
+/// xorshift64*, good enough for sampling and reproducible between runs.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545f4914f6cdd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn uniform_c(&mut self) -> (f64, f64) {
+        (4.0 * self.next_f64() - 2.0, 4.0 * self.next_f64() - 2.0)
+    }
+}
+
+/// The histogram bins of a canvas, with one channel per iteration limit.
+struct Histogram {
+    left: f64,
+    top: f64,
+    width: f64,
+    height: f64,
+    pixel_width: u32,
+    pixel_height: u32,
+    limits: Vec<u32>,
+}
+
+impl Histogram {
+    fn new(canvas_size: &CanvasSize, limits: &[u32]) -> Histogram {
+        Histogram {
+            left: to_f64(&canvas_size.left),
+            top: to_f64(&canvas_size.top),
+            width: to_f64(&canvas_size.width()),
+            height: to_f64(&canvas_size.height()),
+            pixel_width: canvas_size.pixel_width,
+            pixel_height: canvas_size.pixel_height,
+            limits: limits.to_vec(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        (self.pixel_width * self.pixel_height) as usize * self.limits.len()
+    }
+
+    fn pixel(&self, x: f64, y: f64) -> Option<usize> {
+        let px = (x - self.left) / self.width * self.pixel_width as f64;
+        let py = (self.top - y) / self.height * self.pixel_height as f64;
+
+        if px >= 0.0 && py >= 0.0 && px < self.pixel_width as f64 &&
+           py < self.pixel_height as f64 {
+            Some((py as u32 * self.pixel_width + px as u32) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// The bins hit by the orbit of `c`, once for every limit it escapes within.
+    fn hits(&self, cx: f64, cy: f64) -> Vec<usize> {
+        let mut hits = Vec::new();
+        if cx.abs() > 2.0 || cy.abs() > 2.0 || in_cardioid_or_period2_bulb(&cx, &cy) {
+            return hits;
+        }
+
+        let max = *self.limits.iter().max().unwrap();
+        let mut orbit = Vec::new();
+        let escaped = match iterate_with(&cx, &cy, max, |x, y| orbit.push((*x, *y))).0 {
+            Iteration::Escaped(i, _) => i,
+            _ => return hits,
+        };
+
+        let channels = self.limits.len();
+        for &(x, y) in &orbit {
+            if let Some(pixel) = self.pixel(x, y) {
+                for (channel, &limit) in self.limits.iter().enumerate() {
+                    if escaped < limit {
+                        hits.push(pixel * channels + channel);
+                    }
+                }
+            }
+        }
+        hits
+    }
+
+    /// Runs one chain of `samples` values of `c`.
+    fn sample(&self, samples: u64, sampler: Sampler, seed: u64) -> Vec<f64> {
+        let mut rng = Rng::new(seed);
+        let mut bins = vec![0.0; self.len()];
+
+        match sampler {
+            Sampler::Uniform => {
+                for _ in 0..samples {
+                    let (cx, cy) = rng.uniform_c();
+                    for bin in self.hits(cx, cy) {
+                        bins[bin] += 1.0;
+                    }
+                }
+            }
+            Sampler::Metropolis => self.metropolis(samples, &mut rng, &mut bins),
+        }
+        bins
+    }
+
+    /// Samples `c` with a density proportional to the number of bins its orbit
+    /// hits. Every sample is weighted by the inverse of that, so the histogram
+    /// converges to the uniform one up to a constant factor.
+    fn metropolis(&self, samples: u64, rng: &mut Rng, bins: &mut [f64]) {
+        let min_radius = self.width * 1e-4;
+        let max_radius = self.width * 0.1;
+
+        let mut current: Option<((f64, f64), Vec<usize>)> = None;
+        for _ in 0..samples {
+            let (c, hits) = match current.take() {
+                None => {
+                    let c = rng.uniform_c();
+                    let hits = self.hits(c.0, c.1);
+                    if hits.is_empty() {
+                        continue;
+                    }
+                    (c, hits)
+                }
+                Some((c, hits)) => {
+                    let proposal = if rng.next_f64() < LARGE_STEP {
+                        rng.uniform_c()
+                    } else {
+                        // Radii spread evenly on a log scale between the bounds.
+                        let r = max_radius *
+                                (min_radius / max_radius).powf(rng.next_f64());
+                        let phi = 2.0 * ::std::f64::consts::PI * rng.next_f64();
+                        (c.0 + r * phi.cos(), c.1 + r * phi.sin())
+                    };
+                    let proposal_hits = self.hits(proposal.0, proposal.1);
+
+                    if rng.next_f64() * (hits.len() as f64) < proposal_hits.len() as f64 {
+                        (proposal, proposal_hits)
+                    } else {
+                        (c, hits)
+                    }
+                }
+            };
+
+            let weight = 1.0 / hits.len() as f64;
+            for &bin in &hits {
+                bins[bin] += weight;
+            }
+            current = Some((c, hits));
+        }
+    }
+
+    fn accumulate(&self, samples: u64, sampler: Sampler) -> Vec<f64> {
+        let mut chunks: Vec<Vec<f64>> = Vec::new();
+        (0..CHUNKS)
+            .into_par_iter()
+            .weight_max()
+            .map(|i| {
+                let (i, chunks) = (i as u64, CHUNKS as u64);
+                let n = samples / chunks + if i < samples % chunks { 1 } else { 0 };
+                self.sample(n, sampler, i + 1)
+            })
+            .collect_into(&mut chunks);
+
+        let mut bins = vec![0.0; self.len()];
+        for chunk in chunks {
+            for (bin, x) in bins.iter_mut().zip(chunk) {
+                *bin += x;
+            }
+        }
+        bins
+    }
+}
+
+/// Renders the Buddhabrot: how often the orbits of `samples` values of `c`
+/// that escape within `max_iterations` pass through every pixel.
+pub fn calculate_buddhabrot(canvas_size: CanvasSize,
+                            max_iterations: u32,
+                            samples: u64,
+                            sampler: Sampler)
+                            -> Vec<f64> {
+    Histogram::new(&canvas_size, &[max_iterations]).accumulate(samples, sampler)
+}
+
+/// Renders the Nebulabrot, a Buddhabrot for each of the three iteration limits.
+/// Every pixel holds the densities in the order of `max_iterations`.
+pub fn calculate_nebulabrot(canvas_size: CanvasSize,
+                            max_iterations: [u32; 3],
+                            samples: u64,
+                            sampler: Sampler)
+                            -> Vec<[f64; 3]> {
+    Histogram::new(&canvas_size, &max_iterations)
+        .accumulate(samples, sampler)
+        .chunks(3)
+        .map(|d| [d[0], d[1], d[2]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut rng = Rng::new(1);
+        let v: Vec<f64> = (0..10000).map(|_| rng.next_f64()).collect();
+
+        assert!(v.iter().cloned().fold(1.0, f64::min) >= 0.0);
+        assert!(v.iter().cloned().fold(0.0, f64::max) < 1.0);
+        assert!((v.iter().sum::<f64>() / 10000.0 - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_buddhabrot_symmetry() {
+        let c = CanvasSize::new_from_center(40, 40, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+        let v = calculate_buddhabrot(c, 200, 50000, Sampler::Uniform);
+        let top: f64 = v[..800].iter().sum();
+        let bottom: f64 = v[800..].iter().sum();
+
+        // Conjugate values of c have mirrored orbits.
+        assert!(top > 0.0);
+        assert!((top / bottom - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_nebulabrot_channels() {
+        let c = CanvasSize::new_from_center(30, 20, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+        let v = calculate_nebulabrot(c.clone(), [20, 100, 500], 20000, Sampler::Uniform);
+
+        // Every orbit that escapes within a limit also escapes within the larger ones.
+        assert!(v.iter().all(|d| d[0] <= d[1] && d[1] <= d[2]));
+        assert!(v.iter().any(|d| d[0] < d[2]));
+
+        let single = calculate_buddhabrot(c, 100, 20000, Sampler::Uniform);
+        assert_eq!(v.iter().map(|d| d[1]).collect::<Vec<_>>(), single);
+    }
+
+    #[test]
+    fn test_metropolis_finds_zoomed_canvas() {
+        let c = CanvasSize::new_from_center(30, 20, [mpfr!(-0.75), mpfr!(0.1)], mpfr!(20.0));
+        let uniform = calculate_buddhabrot(c.clone(), 500, 20000, Sampler::Uniform);
+        let metropolis = calculate_buddhabrot(c, 500, 20000, Sampler::Metropolis);
+        let lit = |v: &[f64]| v.iter().filter(|&&d| d > 0.0).count();
+
+        assert!(lit(&metropolis) > 2 * lit(&uniform));
+    }
+}
@@ -0,0 +1,394 @@
+use num::complex::Complex64;
+use rust_mpfr::mpfr::*;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use super::{Real, to_f64, with_prec};
+
+/// Splits `x` into a mantissa in `[0.5, 1)` and a binary exponent.
+fn frexp(x: f64) -> (f64, i64) {
+    if x == 0.0 || !x.is_finite() {
+        return (x, 0);
+    }
+
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64;
+    if exponent == 0 {
+        // Subnormal, scale it into the normal range first.
+        let (m, e) = frexp(x * 2f64.powi(64));
+        return (m, e - 64);
+    }
+
+    (f64::from_bits((bits & !(0x7ff << 52)) | (1022 << 52)), exponent - 1022)
+}
+
+/// Computes `m * 2^e`, flushing to zero or infinity outside the range of `f64`.
+fn ldexp(m: f64, e: i64) -> f64 {
+    if e < -2200 {
+        m * 0.0
+    } else if e > 2200 {
+        m * f64::INFINITY
+    } else {
+        let half = (e / 2) as i32;
+        m * 2f64.powi(half) * 2f64.powi(e as i32 - half)
+    }
+}
+
+/// Floating point number with an `f64` mantissa and an `i64` binary exponent.
+///
+/// Has the precision of an `f64`, but does not underflow at the deep zoom
+/// levels where the pixel spacing drops below `1e-308`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloatExp {
+    mantissa: f64,
+    exponent: i64,
+}
+
+impl FloatExp {
+    pub fn new(mantissa: f64, exponent: i64) -> FloatExp {
+        let (m, e) = frexp(mantissa);
+        FloatExp {
+            mantissa: m,
+            exponent: if m == 0.0 { 0 } else { exponent + e },
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        ldexp(self.mantissa, self.exponent)
+    }
+
+    pub fn exponent(&self) -> i64 {
+        self.exponent
+    }
+
+    /// Converts exactly to MPFR with `prec` bits.
+    pub fn to_mpfr(&self, prec: usize) -> Mpfr {
+        const STEP: i64 = 512;
+
+        let mut x = with_prec(&Mpfr::from(self.mantissa), prec);
+        let mut exponent = self.exponent;
+        while exponent > STEP {
+            x = x * 2f64.powi(STEP as i32);
+            exponent -= STEP;
+        }
+        while exponent < -STEP {
+            x = x * 2f64.powi(-STEP as i32);
+            exponent += STEP;
+        }
+        x * 2f64.powi(exponent as i32)
+    }
+}
+
+impl From<f64> for FloatExp {
+    fn from(x: f64) -> FloatExp {
+        FloatExp::new(x, 0)
+    }
+}
+
+impl<'a> From<&'a Mpfr> for FloatExp {
+    fn from(x: &'a Mpfr) -> FloatExp {
+        const STEP: i32 = 512;
+
+        if *x == Mpfr::from(0.0) {
+            return FloatExp::from(0.0);
+        }
+        // No scaling brings infinities or NaN into range. Large finite values
+        // also convert to an infinite `f64`, but differ from it.
+        let f = to_f64(x);
+        if f.is_nan() || f.is_infinite() && *x == Mpfr::from(f) {
+            return FloatExp::from(f);
+        }
+
+        let mut x = x.clone();
+        let mut exponent = 0i64;
+        while to_f64(&x).abs() < 2f64.powi(-STEP) {
+            x = x * 2f64.powi(STEP);
+            exponent -= STEP as i64;
+        }
+        while to_f64(&x).abs() > 2f64.powi(STEP) {
+            x = x * 2f64.powi(-STEP);
+            exponent += STEP as i64;
+        }
+
+        FloatExp::new(to_f64(&x), exponent)
+    }
+}
+
+impl Add for FloatExp {
+    type Output = FloatExp;
+
+    fn add(self, other: FloatExp) -> FloatExp {
+        if self.mantissa == 0.0 {
+            return other;
+        }
+        if other.mantissa == 0.0 {
+            return self;
+        }
+
+        let (big, small) = if self.exponent >= other.exponent {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let shift = big.exponent - small.exponent;
+        if shift > 64 {
+            return big;
+        }
+
+        FloatExp::new(big.mantissa + ldexp(small.mantissa, -shift), big.exponent)
+    }
+}
+
+impl Mul for FloatExp {
+    type Output = FloatExp;
+
+    fn mul(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(self.mantissa * other.mantissa, self.exponent + other.exponent)
+    }
+}
+
+impl Div for FloatExp {
+    type Output = FloatExp;
+
+    fn div(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(self.mantissa / other.mantissa, self.exponent - other.exponent)
+    }
+}
+
+impl Neg for FloatExp {
+    type Output = FloatExp;
+
+    fn neg(self) -> FloatExp {
+        FloatExp {
+            mantissa: -self.mantissa,
+            exponent: self.exponent,
+        }
+    }
+}
+
+impl Sub for FloatExp {
+    type Output = FloatExp;
+
+    fn sub(self, other: FloatExp) -> FloatExp {
+        self + -other
+    }
+}
+
+impl Real for FloatExp {
+    fn from_f64(x: f64) -> FloatExp {
+        FloatExp::from(x)
+    }
+
+    fn from_mpfr(x: &Mpfr) -> FloatExp {
+        FloatExp::from(x)
+    }
+
+    fn to_f64(&self) -> f64 {
+        FloatExp::to_f64(self)
+    }
+
+    fn prec(&self) -> usize {
+        53
+    }
+
+    fn exponent(&self) -> i64 {
+        if self.mantissa == 0.0 {
+            i64::MIN
+        } else {
+            self.exponent
+        }
+    }
+
+    fn plus(&self, other: &FloatExp) -> FloatExp {
+        *self + *other
+    }
+
+    fn minus(&self, other: &FloatExp) -> FloatExp {
+        *self - *other
+    }
+
+    fn times(&self, other: &FloatExp) -> FloatExp {
+        *self * *other
+    }
+
+    fn recip(&self) -> FloatExp {
+        FloatExp::from(1.0) / *self
+    }
+}
+
+impl PartialOrd for FloatExp {
+    fn partial_cmp(&self, other: &FloatExp) -> Option<Ordering> {
+        (*self - *other).mantissa.partial_cmp(&0.0)
+    }
+}
+
+impl fmt::Display for FloatExp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mantissa == 0.0 {
+            return write!(f, "0");
+        }
+
+        let log10 = self.mantissa.abs().log10() + self.exponent as f64 * 2f64.log10();
+        let exponent10 = log10.floor();
+        let mantissa10 = self.mantissa.signum() * 10f64.powf(log10 - exponent10);
+        write!(f, "{}e{}", mantissa10, exponent10)
+    }
+}
+
+/// Complex number made of two `FloatExp`s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComplexExp {
+    pub re: FloatExp,
+    pub im: FloatExp,
+}
+
+impl ComplexExp {
+    pub fn new(re: FloatExp, im: FloatExp) -> ComplexExp {
+        ComplexExp { re: re, im: im }
+    }
+
+    pub fn to_complex64(&self) -> Complex64 {
+        Complex64::new(self.re.to_f64(), self.im.to_f64())
+    }
+
+    pub fn norm(&self) -> FloatExp {
+        let exponent = ::std::cmp::max(self.re.exponent, self.im.exponent);
+        let re = ldexp(self.re.mantissa, self.re.exponent - exponent);
+        let im = ldexp(self.im.mantissa, self.im.exponent - exponent);
+        FloatExp::new(re.hypot(im), exponent)
+    }
+}
+
+impl From<Complex64> for ComplexExp {
+    fn from(c: Complex64) -> ComplexExp {
+        ComplexExp::new(FloatExp::from(c.re), FloatExp::from(c.im))
+    }
+}
+
+impl Add for ComplexExp {
+    type Output = ComplexExp;
+
+    fn add(self, other: ComplexExp) -> ComplexExp {
+        ComplexExp::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for ComplexExp {
+    type Output = ComplexExp;
+
+    fn sub(self, other: ComplexExp) -> ComplexExp {
+        ComplexExp::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for ComplexExp {
+    type Output = ComplexExp;
+
+    fn mul(self, other: ComplexExp) -> ComplexExp {
+        ComplexExp::new(self.re * other.re - self.im * other.im,
+                        self.re * other.im + self.im * other.re)
+    }
+}
+
+impl Div for ComplexExp {
+    type Output = ComplexExp;
+
+    fn div(self, other: ComplexExp) -> ComplexExp {
+        let norm_sqr = other.re * other.re + other.im * other.im;
+        ComplexExp::new((self.re * other.re + self.im * other.im) / norm_sqr,
+                        (self.im * other.re - self.re * other.im) / norm_sqr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    #[test]
+    fn test_frexp() {
+        assert_eq!(frexp(1.0), (0.5, 1));
+        assert_eq!(frexp(-3.0), (-0.75, 2));
+        assert_eq!(frexp(0.0), (0.0, 0));
+        assert_eq!(frexp(5e-324), (0.5, -1073));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = FloatExp::from(1.5);
+        let b = FloatExp::from(-0.25);
+
+        assert_eq!((a + b).to_f64(), 1.25);
+        assert_eq!((a - b).to_f64(), 1.75);
+        assert_eq!((a * b).to_f64(), -0.375);
+        assert_eq!((a / b).to_f64(), -6.0);
+        assert_eq!((-a).to_f64(), -1.5);
+        assert!(b < a);
+        assert!(FloatExp::new(1.0, -5000) > FloatExp::new(-1.0, 5000));
+    }
+
+    #[test]
+    fn test_beyond_f64_range() {
+        let tiny = FloatExp::from(1e-300) * FloatExp::from(1e-300);
+
+        assert_eq!(tiny.to_f64(), 0.0);
+        assert!(tiny > FloatExp::from(0.0));
+        assert!(((tiny * FloatExp::from(1e300)).to_f64() - 1e-300).abs() < 1e-315);
+        assert_eq!(format!("{}", FloatExp::new(1.0, -4946)).rsplit('e').next(), Some("-1489"));
+    }
+
+    #[test]
+    fn test_complex_norm() {
+        let z = ComplexExp::new(FloatExp::new(3.0, -2000), FloatExp::new(-4.0, -2000));
+
+        assert_eq!(ComplexExp::from(Complex64::new(3.0, 4.0)).norm().to_f64(), 5.0);
+        assert_eq!((z.norm() / FloatExp::new(1.0, -2000)).to_f64(), 5.0);
+        assert_eq!(ComplexExp::from(Complex64::new(0.0, 0.0)).norm().to_f64(), 0.0);
+    }
+
+    #[test]
+    fn test_complex_division() {
+        let a = ComplexExp::from(Complex64::new(1.0, 2.0));
+        let b = ComplexExp::new(FloatExp::new(3.0, -2000), FloatExp::new(-1.0, -2000));
+
+        assert_eq!((a * b / b).to_complex64(), Complex64::new(1.0, 2.0));
+        assert_eq!((a / a).to_complex64(), Complex64::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn test_from_mpfr() {
+        let x = Mpfr::new2_from_str(128, "3e-1489", 10).unwrap();
+        let f = FloatExp::from(&x);
+
+        assert_eq!(format!("{}", f).rsplit('e').next(), Some("-1489"));
+        assert!((f * FloatExp::new(1.0, 4946)).to_f64() > 0.0);
+        assert_eq!(FloatExp::from(&mpfr!(-0.75)).to_f64(), -0.75);
+    }
+
+    #[test]
+    fn test_from_mpfr_beyond_range() {
+        let huge = Mpfr::new2_from_str(128, "-3e1489", 10).unwrap();
+        assert_eq!(format!("{}", FloatExp::from(&huge)).rsplit('e').next(), Some("1489"));
+
+        for &inf in &[f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(FloatExp::from(&Mpfr::from(inf)).to_f64(), inf);
+        }
+    }
+
+    #[test]
+    fn test_to_mpfr() {
+        let x = FloatExp::new(-0.75, -3000);
+
+        assert_eq!(FloatExp::from(&x.to_mpfr(128)), x);
+        assert_eq!(x.to_mpfr(128).get_prec(), 128);
+        assert_eq!(FloatExp::from(1.5).to_mpfr(53), mpfr!(1.5));
+    }
+
+    #[test]
+    fn test_iterate() {
+        for &(x, y) in &[(-0.5, 0.0), (0.3, 0.1), (-1.8, 0.01), (0.25, 0.5)] {
+            assert_eq!(iterate::<FloatExp>(FloatExp::from(x), FloatExp::from(y), 100),
+                       iterate::<f64>(x, y, 100));
+        }
+    }
+}
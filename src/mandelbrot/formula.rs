@@ -0,0 +1,244 @@
+use super::{CanvasSize, Iteration, Real, in_cardioid_or_period2_bulb, iterate_from, render,
+            smooth_iteration_count_for_degree};
+
+/// Escape-time iteration `z ↦ f(z, c)`, started from `z = 0`.
+pub trait Formula: Sync {
+    /// One step of the iteration.
+    fn step<T: Real>(&self, x: &T, y: &T, cx: &T, cy: &T) -> (T, T);
+
+    /// Escaped orbits grow like `|z| ↦ |z|^degree`.
+    fn degree(&self) -> u32 {
+        2
+    }
+
+    /// Period of the attracting cycle, if `c` is known to be interior without
+    /// iterating it.
+    fn known_period<T: Real>(&self, _cx: &T, _cy: &T) -> Option<u32> {
+        None
+    }
+}
+
+/// `z² + c`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mandelbrot;
+
+impl Formula for Mandelbrot {
+    fn step<T: Real>(&self, x: &T, y: &T, cx: &T, cy: &T) -> (T, T) {
+        ((x.square() - y.square()).plus(cx), x.times(y).double().plus(cy))
+    }
+
+    fn known_period<T: Real>(&self, cx: &T, cy: &T) -> Option<u32> {
+        if !in_cardioid_or_period2_bulb(cx, cy) {
+            return None;
+        }
+
+        let x = cx.plus(&T::from_f64(1.0));
+        Some(if T::norm_sqr(&x, cy).lt_f64(0.0625) { 2 } else { 1 })
+    }
+}
+
+/// `zⁿ + c` for an integer `n` of at least 2.
+#[derive(Clone, Copy, Debug)]
+pub struct Multibrot(u32);
+
+impl Multibrot {
+    /// Panics for `n < 2`, where the smooth iteration count is undefined.
+    pub fn new(n: u32) -> Multibrot {
+        assert!(n >= 2, "Multibrot exponent {} below 2", n);
+        Multibrot(n)
+    }
+}
+
+impl Formula for Multibrot {
+    fn step<T: Real>(&self, x: &T, y: &T, cx: &T, cy: &T) -> (T, T) {
+        let mut re = x.clone();
+        let mut im = y.clone();
+        for _ in 1..self.0 {
+            let re_next = re.times(x) - im.times(y);
+            im = re.times(y) + im.times(x);
+            re = re_next;
+        }
+
+        (re.plus(cx), im.plus(cy))
+    }
+
+    fn degree(&self) -> u32 {
+        self.0
+    }
+}
+
+/// `(|Re z| + i|Im z|)² + c`. The ship is upright when the imaginary axis points down.
+#[derive(Clone, Copy, Debug)]
+pub struct BurningShip;
+
+impl Formula for BurningShip {
+    fn step<T: Real>(&self, x: &T, y: &T, cx: &T, cy: &T) -> (T, T) {
+        ((x.square() - y.square()).plus(cx), x.times(y).abs().double().plus(cy))
+    }
+}
+
+/// The Tricorn or Mandelbar set, `conj(z)² + c`.
+#[derive(Clone, Copy, Debug)]
+pub struct Tricorn;
+
+impl Formula for Tricorn {
+    fn step<T: Real>(&self, x: &T, y: &T, cx: &T, cy: &T) -> (T, T) {
+        ((x.square() - y.square()).plus(cx), cy.minus(&x.times(y).double()))
+    }
+}
+
+/// The Celtic Mandelbrot set, `|Re z²| + i Im z² + c`.
+#[derive(Clone, Copy, Debug)]
+pub struct Celtic;
+
+impl Formula for Celtic {
+    fn step<T: Real>(&self, x: &T, y: &T, cx: &T, cy: &T) -> (T, T) {
+        ((x.square() - y.square()).abs().plus(cx), x.times(y).double().plus(cy))
+    }
+}
+
+/// Iterates a single point of `formula`, reporting the period of interior points
+/// when found.
+pub fn iterate_formula<T: Real, F: Formula>(formula: &F,
+                                            x0: T,
+                                            y0: T,
+                                            max_iterations: u32)
+                                            -> Iteration {
+    if let Some(period) = formula.known_period(&x0, &y0) {
+        return Iteration::Periodic(period);
+    }
+
+    iterate_from(formula, T::from_f64(0.0), T::from_f64(0.0), &x0, &y0, max_iterations, |_, _| {})
+        .0
+}
+
+/// The orbit of a point. Orbits found periodic are continued along their cycle
+/// up to `max_iterations`.
+pub fn iterate_all_formula<T: Real, F: Formula>(formula: &F,
+                                                x0: T,
+                                                y0: T,
+                                                max_iterations: u32)
+                                                -> Vec<(T, T)> {
+    let mut v = Vec::new();
+
+    let (result, _, _) = iterate_from(formula,
+                                      T::from_f64(0.0),
+                                      T::from_f64(0.0),
+                                      &x0,
+                                      &y0,
+                                      max_iterations,
+                                      |x, y| v.push((x.clone(), y.clone())));
+    if let Iteration::Periodic(period) = result {
+        for i in v.len()..max_iterations as usize {
+            let z = v[i - period as usize].clone();
+            v.push(z);
+        }
+    }
+
+    v
+}
+
+/// Renders the canvas for `formula` in `T`, with smooth iteration counts like
+/// `calculate_all`.
+pub fn calculate_all_formula<T: Real, F: Formula>(canvas_size: CanvasSize,
+                                                  formula: &F,
+                                                  max_iterations: u32)
+                                                  -> Vec<Option<f64>> {
+    render(&canvas_size, |x: T, y| match iterate_formula(formula, x, y, max_iterations) {
+        Iteration::Escaped(i, norm_sqr) => {
+            Some(smooth_iteration_count_for_degree(i, norm_sqr, formula.degree()))
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+    use rust_mpfr::mpfr::*;
+
+    fn step<F: Formula>(formula: F) -> (f64, f64) {
+        formula.step(&-1.0, &2.0, &0.5, &0.25)
+    }
+
+    #[test]
+    fn test_step() {
+        // z = -1 + 2i, z² = -3 - 4i, z³ = 11 - 2i and c = 0.5 + 0.25i.
+        assert_eq!(step(Mandelbrot), (-2.5, -3.75));
+        assert_eq!(step(Multibrot::new(2)), (-2.5, -3.75));
+        assert_eq!(step(Multibrot::new(3)), (11.5, -1.75));
+        assert_eq!(step(BurningShip), (-2.5, 4.25));
+        assert_eq!(step(Tricorn), (-2.5, 4.25));
+        assert_eq!(step(Celtic), (3.5, -3.75));
+
+        assert_eq!(Mandelbrot.step(&mpfr!(-1.0), &mpfr!(2.0), &mpfr!(0.5), &mpfr!(0.25)),
+                   (mpfr!(-2.5), mpfr!(-3.75)));
+        assert_eq!(Celtic.step(&mpfr!(-1.0), &mpfr!(2.0), &mpfr!(0.5), &mpfr!(0.25)),
+                   (mpfr!(3.5), mpfr!(-3.75)));
+    }
+
+    #[test]
+    fn test_real_axis_matches_mandelbrot() {
+        // Every formula but the Multibrot is z² + c for real z and c.
+        for &x in &[-1.9, -1.0, -0.5, 0.2, 0.26, 0.3, 1.0] {
+            let expected = iterate::<f64>(x, 0.0, 1000);
+
+            assert_eq!(iterate_formula(&BurningShip, x, 0.0, 1000).escaped(), expected);
+            assert_eq!(iterate_formula(&Tricorn, x, 0.0, 1000).escaped(), expected);
+            assert_eq!(iterate_formula(&Celtic, x, 0.0, 1000).escaped(), expected);
+        }
+    }
+
+    #[test]
+    fn test_multibrot() {
+        // The cubic Multibrot set meets the real axis in about [-0.3849, 0.3849].
+        assert_eq!(iterate_formula(&Multibrot::new(3), 0.38, 0.0, 1000).escaped(), None);
+        assert_eq!(iterate_formula(&Multibrot::new(3), -0.38, 0.0, 1000).escaped(), None);
+        assert!(iterate_formula(&Multibrot::new(3), 0.39, 0.0, 1000).escaped().is_some());
+        assert!(iterate_formula(&Multibrot::new(3), -0.39, 0.0, 1000).escaped().is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_multibrot_rejects_linear() {
+        Multibrot::new(1);
+    }
+
+    #[test]
+    fn test_tricorn_symmetry() {
+        // The Tricorn is symmetric under rotation by a third of a turn.
+        let (sin, cos) = (2.0 * ::std::f64::consts::PI / 3.0).sin_cos();
+
+        for &(x, y) in &[(0.3, 0.4), (-1.2, 0.1), (0.1, -0.9), (-0.4, -0.45)] {
+            let (xr, yr) = (x * cos - y * sin, x * sin + y * cos);
+            assert_eq!(iterate_formula(&Tricorn, x, y, 1000).escaped(),
+                       iterate_formula(&Tricorn, xr, yr, 1000).escaped());
+        }
+    }
+
+    fn renders<F: Formula>(c: &CanvasSize, formula: &F) -> (Vec<Option<f64>>, Vec<Option<f64>>) {
+        let f64 = calculate_all_formula::<f64, _>(c.clone(), formula, 100);
+        let mpfr = calculate_all_formula::<Mpfr, _>(c.clone(), formula, 100);
+
+        assert!(f64.contains(&None));
+        (f64, mpfr)
+    }
+
+    #[test]
+    fn test_calculate_all_formula() {
+        let c = CanvasSize::new_from_center(60, 40, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+
+        assert_eq!(calculate_all_formula::<f64, _>(c.clone(), &Mandelbrot, 200),
+                   calculate_all_f64(c.clone(), 200));
+
+        // The folds make the orbits near the boundary chaotic, so a few pixels
+        // end up apart from rounding alone.
+        let (f64, mpfr) = renders(&c, &BurningShip);
+        assert_matches_mpfr(&f64, &mpfr);
+        let (f64, mpfr) = renders(&c, &Celtic);
+        assert_matches_mpfr(&f64, &mpfr);
+        let (f64, mpfr) = renders(&c, &Tricorn);
+        assert_eq!(mismatches(&f64, &mpfr), 0);
+    }
+}
@@ -0,0 +1,253 @@
+use num::complex::Complex64;
+use rayon::prelude::*;
+use rust_mpfr::mpfr::*;
+use super::{CanvasSize, Cycle, Iteration, OrbitCache, Real, calculate_all_delta_cached,
+            in_cardioid_or_period2_bulb, iterate_with, perturbed_cycle, render};
+use super::floatexp::{ComplexExp, FloatExp};
+
+/// The attracting cycle an interior point ends up in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interior {
+    pub period: u32,
+    /// Derivative of the cycle by `z`, inside the unit disk.
+    pub multiplier: Complex64,
+    /// Interior distance estimate. The distance to the boundary lies between a
+    /// quarter of the estimate and the estimate itself.
+    pub distance: FloatExp,
+}
+
+impl Interior {
+    /// Follows the derivatives of `z`, `dz/dz`, `dz/dc`, `d²z/dz²` and `d²z/dcdz`
+    /// once around the cycle through `points`.
+    fn from_cycle(points: &[Complex64]) -> Interior {
+        let zero = ComplexExp::from(Complex64::new(0.0, 0.0));
+        let one = ComplexExp::from(Complex64::new(1.0, 0.0));
+        let mut dz = Complex64::new(1.0, 0.0);
+        let mut dzz = Complex64::new(0.0, 0.0);
+        let mut dc = zero;
+        let mut dcz = zero;
+
+        for &z in points {
+            let two_z = ComplexExp::from(z * 2.0);
+            dcz = two_z * dcz + ComplexExp::from(dz * 2.0) * dc;
+            dzz = z * dzz * 2.0 + dz * dz * 2.0;
+            dc = two_z * dc + one;
+            dz = z * dz * 2.0;
+        }
+
+        let denominator = ComplexExp::from(dzz / (1.0 - dz)) * dc + dcz;
+
+        Interior {
+            period: points.len() as u32,
+            multiplier: dz,
+            distance: FloatExp::from(1.0 - dz.norm_sqr()) / denominator.norm(),
+        }
+    }
+}
+
+/// Iterates a single point and, if it is found periodic, works out its cycle.
+///
+/// Points in the main cardioid or the period-2 bulb skip the iteration, their
+/// cycles are known in closed form.
+pub fn iterate_with_interior<T: Real>(x0: T,
+                                      y0: T,
+                                      max_iterations: u32)
+                                      -> (Iteration, Option<Interior>) {
+    if in_cardioid_or_period2_bulb(&x0, &y0) {
+        let c = Complex64::new(x0.to_f64(), y0.to_f64());
+        let (period, z) = if (c + 1.0).norm_sqr() < 0.0625 {
+            (2, ((-3.0 - c * 4.0).sqrt() - 1.0) / 2.0)
+        } else {
+            (1, (1.0 - (1.0 - c * 4.0).sqrt()) / 2.0)
+        };
+        let points = if period == 2 { vec![z, z * z + c] } else { vec![z] };
+
+        return (Iteration::Periodic(period), Some(Interior::from_cycle(&points)));
+    }
+
+    iterate_cycle(x0, y0, max_iterations)
+}
+
+fn iterate_cycle<T: Real>(x0: T, y0: T, max_iterations: u32) -> (Iteration, Option<Interior>) {
+    let (result, mut x, mut y) = iterate_with(&x0, &y0, max_iterations, |_, _| {});
+
+    match result {
+        Iteration::Periodic(period) => {
+            let mut points = Vec::with_capacity(period as usize);
+            for _ in 0..period {
+                points.push(Complex64::new(x.to_f64(), y.to_f64()));
+
+                let xtemp = (x.square() - y.square()).plus(&x0);
+                y = x.times(&y).double().plus(&y0);
+                x = xtemp;
+            }
+            (result, Some(Interior::from_cycle(&points)))
+        }
+        _ => (result, None),
+    }
+}
+
+/// Renders the smooth iteration counts in `T` like `calculate_all`, together
+/// with the cycles of the interior pixels.
+pub fn calculate_all_interior<T: Real>(canvas_size: CanvasSize,
+                                       max_iterations: u32)
+                                       -> (Vec<Option<f64>>, Vec<Option<Interior>>) {
+    render(&canvas_size, |x: T, y| {
+            let (result, interior) = iterate_with_interior(x, y, max_iterations);
+            (result.smooth(), interior)
+        })
+        .into_iter()
+        .unzip()
+}
+
+/// Renders the smooth iteration counts by perturbation, then finds the cycles
+/// of the pixels that did not escape on their perturbed orbits.
+pub fn calculate_all_delta_interior(canvas_size: CanvasSize,
+                                    max_iterations: u32)
+                                    -> (Vec<Option<f64>>, Vec<Option<Interior>>) {
+    calculate_all_delta_interior_cached(canvas_size, max_iterations, &mut OrbitCache::new())
+}
+
+/// Like `calculate_all_delta_interior`, with the central reference from `cache`.
+///
+/// Pixels in the main cardioid or the period-2 bulb take the closed form, and
+/// only pixels whose perturbed orbit glitches are iterated in MPFR.
+pub fn calculate_all_delta_interior_cached(canvas_size: CanvasSize,
+                                           max_iterations: u32,
+                                           cache: &mut OrbitCache)
+                                           -> (Vec<Option<f64>>, Vec<Option<Interior>>) {
+    let v = calculate_all_delta_cached(canvas_size.clone(), max_iterations, cache);
+    let orbit = cache.get(&canvas_size, max_iterations);
+
+    let mut interior: Vec<Option<Interior>> = Vec::new();
+    (0..canvas_size.pixel_count())
+        .into_par_iter()
+        .weight_max()
+        .map(|i| {
+            if v[i as usize].is_some() {
+                return None;
+            }
+            let c = canvas_size.coordinates(canvas_size.idx_to_coord(i as usize));
+            if in_cardioid_or_period2_bulb(&c[0], &c[1]) {
+                return iterate_with_interior::<Mpfr>(c[0].clone(), c[1].clone(), max_iterations).1;
+            }
+            match perturbed_cycle(orbit, &c, max_iterations) {
+                Cycle::Points(points) => Some(Interior::from_cycle(&points)),
+                Cycle::Unsettled => None,
+                Cycle::Glitched => iterate_cycle(c[0].clone(), c[1].clone(), max_iterations).1,
+            }
+        })
+        .collect_into(&mut interior);
+    (v, interior)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    #[test]
+    fn test_closed_form_cycles() {
+        let (result, interior) = iterate_with_interior::<f64>(0.0, 0.0, 1000);
+        let interior = interior.unwrap();
+
+        assert_eq!(result, Iteration::Periodic(1));
+        assert_eq!(interior.multiplier, Complex64::new(0.0, 0.0));
+        // The closest boundary point to 0 is the cusp at 1/4.
+        assert!(interior.distance.to_f64() / 4.0 <= 0.25 && 0.25 <= interior.distance.to_f64());
+
+        let (result, interior) = iterate_with_interior::<f64>(-1.0, 0.0, 1000);
+        assert_eq!(result, Iteration::Periodic(2));
+        assert!(interior.unwrap().multiplier.norm() < 1e-12);
+        assert!((interior.unwrap().distance.to_f64() - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_closed_form_matches_iteration() {
+        for &(x, y) in &[(-0.5, 0.3), (0.1, 0.2), (-1.1, 0.1), (-0.9, -0.05)] {
+            let expected = iterate_with_interior::<f64>(x, y, 10000).1.unwrap();
+            let interior = iterate_cycle::<f64>(x, y, 10000).1.unwrap();
+
+            assert_eq!(interior.period, expected.period);
+            assert!((interior.multiplier - expected.multiplier).norm() < 1e-6);
+            assert!((interior.distance.to_f64() / expected.distance.to_f64() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_multiplier() {
+        // A point in the period-3 bulb, well away from its nucleus.
+        let (result, interior) = iterate_with_interior::<f64>(-0.1225, 0.7449, 1000);
+        let interior = interior.unwrap();
+
+        assert_eq!(result, Iteration::Periodic(3));
+        assert!(interior.multiplier.norm() < 1.0);
+        assert!(interior.distance.to_f64() > 0.0);
+
+        let x = Mpfr::new2_from_str(256, "-0.1225", 10).unwrap();
+        let y = Mpfr::new2_from_str(256, "0.7449", 10).unwrap();
+        let mpfr = iterate_with_interior::<Mpfr>(x, y, 1000).1.unwrap();
+
+        assert_eq!(mpfr.period, 3);
+        assert!((mpfr.multiplier - interior.multiplier).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_perturbed_cycles() {
+        // In the period 3 bulb, and around the period 39 minibrot in the seahorse
+        // valley.
+        let n = [Mpfr::new2_from_str(128, "-0.74364230165788594611859731943377783587", 10).unwrap(),
+                 Mpfr::new2_from_str(128, "0.13182651981259472349919301230551915131", 10).unwrap()];
+        let bulb = CanvasSize::new_from_center(30, 20, [mpfr!(-0.1225), mpfr!(0.7449)], mpfr!(10.0));
+        let minibrot = CanvasSize::new_from_center(30, 20, n, mpfr!(1e6));
+        let one = Complex64::new(1.0, 0.0);
+
+        for c in &[bulb, minibrot] {
+            let orbit = ReferenceOrbit::new(c.center(), 5000);
+            let mut interior = 0;
+            for i in (0..c.pixel_count()).step_by(7) {
+                let p = c.coordinates(c.idx_to_coord(i as usize));
+                let found = match perturbed_cycle(&orbit, &p, 5000) {
+                    Cycle::Points(points) => Some(Interior::from_cycle(&points)),
+                    cycle => {
+                        assert_eq!(cycle, Cycle::Unsettled);
+                        None
+                    }
+                };
+
+                match iterate_cycle(p[0].clone(), p[1].clone(), 5000) {
+                    (Iteration::Escaped(..), _) => assert_eq!(found, None),
+                    (_, Some(expected)) => {
+                        // The search in MPFR may come back after a multiple of
+                        // the period.
+                        let found = found.unwrap();
+                        let k = expected.period / found.period;
+                        let multiplier = (0..k).fold(one, |m, _| m * found.multiplier);
+
+                        assert_eq!(found.period * k, expected.period);
+                        assert!((multiplier - expected.multiplier).norm() < 1e-6);
+                        interior += 1;
+                    }
+                    _ => {}
+                }
+            }
+            assert!(interior > 0);
+        }
+    }
+
+    #[test]
+    fn test_calculate_all_interior() {
+        let c = test_canvas();
+        let (v, interior) = calculate_all_interior::<f64>(c.clone(), 1000);
+        let (delta, delta_interior) = calculate_all_delta_interior(c, 1000);
+
+        assert!(interior.iter().any(|i| i.is_some()));
+        for i in 0..v.len() {
+            if interior[i].is_some() {
+                assert_eq!(v[i], None);
+            }
+            assert_eq!(interior[i].map(|i| i.period), delta_interior[i].map(|i| i.period));
+        }
+        assert_matches_mpfr(&delta, &v);
+    }
+}
@@ -0,0 +1,58 @@
+use rust_mpfr::mpfr::*;
+use super::{CanvasSize, Iteration, Mandelbrot, Real, iterate_from, render};
+
+/// Iterates `z² + c` for the fixed `c`, starting from `z0`.
+pub fn iterate_julia<T: Real>(x0: T, y0: T, cx: &T, cy: &T, max_iterations: u32) -> Iteration {
+    iterate_from(&Mandelbrot, x0, y0, cx, cy, max_iterations, |_, _| {}).0
+}
+
+/// Renders the Julia set of `c` in `T`, with every pixel giving the starting `z`.
+/// Pixels hold their smooth iteration count like in `calculate_all`.
+pub fn calculate_all_julia<T: Real>(canvas_size: CanvasSize,
+                                    c: &[Mpfr; 2],
+                                    max_iterations: u32)
+                                    -> Vec<Option<f64>> {
+    let cx = T::from_mpfr(&c[0]);
+    let cy = T::from_mpfr(&c[1]);
+
+    render(&canvas_size, |x: T, y| {
+        iterate_julia(x, y, &cx, &cy, max_iterations).smooth()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    #[test]
+    fn test_iterate_julia() {
+        // The Julia set of 0 is the unit circle.
+        assert_eq!(iterate_julia::<f64>(0.5, 0.5, &0.0, &0.0, 1000), Iteration::Periodic(1));
+        assert_eq!(iterate_julia::<f64>(0.0, 1.0, &0.0, &0.0, 1000), Iteration::Periodic(1));
+        assert_eq!(iterate_julia::<f64>(1.5, 0.0, &0.0, &0.0, 1000).escaped(), Some(4));
+
+        assert_eq!(iterate_julia::<f64>(0.0, 0.0, &-1.0, &0.0, 1000), Iteration::Periodic(2));
+        assert_eq!(iterate_julia::<f64>(300.0, 0.0, &-1.0, &0.0, 1000).escaped(), Some(0));
+    }
+
+    #[test]
+    fn test_critical_orbit_matches_mandelbrot() {
+        for &(x, y) in &[(0.3, 0.0), (-1.8, 0.01), (0.25, 0.5), (-0.75, 0.1), (-0.1225, 0.7449)] {
+            assert_eq!(iterate_julia::<f64>(0.0, 0.0, &x, &y, 1000).escaped(),
+                       iterate::<f64>(x, y, 1000));
+        }
+    }
+
+    #[test]
+    fn test_julia_f64_matches_mpfr() {
+        let c = CanvasSize::new_from_center(60, 40, [mpfr!(0.0), mpfr!(0.0)], mpfr!(0.75));
+        let julia = [mpfr!(-0.1225), mpfr!(0.7449)];
+        let f64 = calculate_all_julia::<f64>(c.clone(), &julia, 500);
+        let mpfr = calculate_all_julia::<Mpfr>(c, &julia, 500);
+
+        assert!(f64.contains(&None));
+        assert!(f64.iter().filter_map(|&i| i).any(|i| i < 10.0));
+        assert_matches_mpfr(&f64, &mpfr);
+    }
+}
@@ -0,0 +1,414 @@
+use rust_mpfr::mpfr::*;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use super::{Real, to_f64, with_prec};
+
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+fn three_sum(a: f64, b: f64, c: f64) -> (f64, f64, f64) {
+    let (t1, t2) = two_sum(a, b);
+    let (a, t3) = two_sum(c, t1);
+    let (b, c) = two_sum(t2, t3);
+    (a, b, c)
+}
+
+fn three_sum2(a: f64, b: f64, c: f64) -> (f64, f64) {
+    let (t1, t2) = two_sum(a, b);
+    let (a, t3) = two_sum(c, t1);
+    (a, t2 + t3)
+}
+
+/// Double-double number, an unevaluated sum of two `f64` with about 106 bits of
+/// precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(x: f64) -> DoubleDouble {
+        DoubleDouble { hi: x, lo: 0.0 }
+    }
+}
+
+impl<'a> From<&'a Mpfr> for DoubleDouble {
+    fn from(x: &'a Mpfr) -> DoubleDouble {
+        let hi = to_f64(x);
+        let lo = to_f64(&(x - hi));
+        let (hi, lo) = quick_two_sum(hi, lo);
+        DoubleDouble { hi: hi, lo: lo }
+    }
+}
+
+impl From<DoubleDouble> for Mpfr {
+    fn from(x: DoubleDouble) -> Mpfr {
+        with_prec(&Mpfr::from(x.hi), 128) + x.lo
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (s1, s2) = two_sum(self.hi, other.hi);
+        let (t1, t2) = two_sum(self.lo, other.lo);
+        let (s1, s2) = quick_two_sum(s1, s2 + t1);
+        let (hi, lo) = quick_two_sum(s1, s2 + t2);
+        DoubleDouble { hi: hi, lo: lo }
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other.hi);
+        let (hi, lo) = quick_two_sum(p, e + (self.hi * other.lo + self.lo * other.hi));
+        DoubleDouble { hi: hi, lo: lo }
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + -other
+    }
+}
+
+impl Real for DoubleDouble {
+    fn from_f64(x: f64) -> DoubleDouble {
+        DoubleDouble::from(x)
+    }
+
+    fn from_mpfr(x: &Mpfr) -> DoubleDouble {
+        DoubleDouble::from(x)
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.hi
+    }
+
+    fn prec(&self) -> usize {
+        106
+    }
+
+    fn plus(&self, other: &DoubleDouble) -> DoubleDouble {
+        *self + *other
+    }
+
+    fn minus(&self, other: &DoubleDouble) -> DoubleDouble {
+        *self - *other
+    }
+
+    fn times(&self, other: &DoubleDouble) -> DoubleDouble {
+        *self * *other
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &DoubleDouble) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi) {
+            Some(Ordering::Equal) => self.lo.partial_cmp(&other.lo),
+            ordering => ordering,
+        }
+    }
+}
+
+impl fmt::Display for DoubleDouble {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Mpfr::from(*self))
+    }
+}
+
+/// Quad-double number, an unevaluated sum of four `f64` with about 212 bits of
+/// precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuadDouble {
+    x: [f64; 4],
+}
+
+impl QuadDouble {
+    fn renormalize(c0: f64, c1: f64, c2: f64, c3: f64, c4: f64) -> QuadDouble {
+        let (s, c4) = quick_two_sum(c3, c4);
+        let (s, c3) = quick_two_sum(c2, s);
+        let (s, c2) = quick_two_sum(c1, s);
+        let (c0, c1) = quick_two_sum(c0, s);
+
+        let mut s = [c0, c1, 0.0, 0.0];
+        if s[1] != 0.0 {
+            let (s1, s2) = quick_two_sum(s[1], c2);
+            s[1] = s1;
+            s[2] = s2;
+            if s[2] != 0.0 {
+                let (s2, s3) = quick_two_sum(s[2], c3);
+                s[2] = s2;
+                s[3] = s3;
+                if s[3] != 0.0 {
+                    s[3] += c4;
+                } else {
+                    s[2] += c4;
+                }
+            } else {
+                let (s1, s2) = quick_two_sum(s[1], c3);
+                s[1] = s1;
+                s[2] = s2;
+                if s[2] != 0.0 {
+                    let (s2, s3) = quick_two_sum(s[2], c4);
+                    s[2] = s2;
+                    s[3] = s3;
+                } else {
+                    let (s1, s2) = quick_two_sum(s[1], c4);
+                    s[1] = s1;
+                    s[2] = s2;
+                }
+            }
+        } else {
+            let (s0, s1) = quick_two_sum(s[0], c2);
+            s[0] = s0;
+            s[1] = s1;
+            if s[1] != 0.0 {
+                let (s1, s2) = quick_two_sum(s[1], c3);
+                s[1] = s1;
+                s[2] = s2;
+                if s[2] != 0.0 {
+                    let (s2, s3) = quick_two_sum(s[2], c4);
+                    s[2] = s2;
+                    s[3] = s3;
+                } else {
+                    let (s1, s2) = quick_two_sum(s[1], c4);
+                    s[1] = s1;
+                    s[2] = s2;
+                }
+            } else {
+                let (s0, s1) = quick_two_sum(s[0], c3);
+                s[0] = s0;
+                s[1] = s1;
+                if s[1] != 0.0 {
+                    let (s1, s2) = quick_two_sum(s[1], c4);
+                    s[1] = s1;
+                    s[2] = s2;
+                } else {
+                    let (s0, s1) = quick_two_sum(s[0], c4);
+                    s[0] = s0;
+                    s[1] = s1;
+                }
+            }
+        }
+
+        QuadDouble { x: s }
+    }
+}
+
+impl From<f64> for QuadDouble {
+    fn from(x: f64) -> QuadDouble {
+        QuadDouble { x: [x, 0.0, 0.0, 0.0] }
+    }
+}
+
+impl<'a> From<&'a Mpfr> for QuadDouble {
+    fn from(x: &'a Mpfr) -> QuadDouble {
+        let mut rest = x.clone();
+        let mut c = [0.0; 4];
+        for limb in &mut c {
+            *limb = to_f64(&rest);
+            rest = rest - *limb;
+        }
+        QuadDouble::renormalize(c[0], c[1], c[2], c[3], to_f64(&rest))
+    }
+}
+
+impl From<QuadDouble> for Mpfr {
+    fn from(x: QuadDouble) -> Mpfr {
+        with_prec(&Mpfr::from(x.x[0]), 256) + x.x[1] + x.x[2] + x.x[3]
+    }
+}
+
+impl Add for QuadDouble {
+    type Output = QuadDouble;
+
+    fn add(self, other: QuadDouble) -> QuadDouble {
+        let (a, b) = (self.x, other.x);
+        let (s0, t0) = two_sum(a[0], b[0]);
+        let (s1, t1) = two_sum(a[1], b[1]);
+        let (s2, t2) = two_sum(a[2], b[2]);
+        let (s3, t3) = two_sum(a[3], b[3]);
+
+        let (s1, t0) = two_sum(s1, t0);
+        let (s2, t0, t1) = three_sum(s2, t0, t1);
+        let (s3, t0) = three_sum2(s3, t0, t2);
+        let t0 = t0 + t1 + t3;
+
+        QuadDouble::renormalize(s0, s1, s2, s3, t0)
+    }
+}
+
+impl Mul for QuadDouble {
+    type Output = QuadDouble;
+
+    fn mul(self, other: QuadDouble) -> QuadDouble {
+        let (a, b) = (self.x, other.x);
+        let (p0, q0) = two_prod(a[0], b[0]);
+        let (p1, q1) = two_prod(a[0], b[1]);
+        let (p2, q2) = two_prod(a[1], b[0]);
+        let (p3, q3) = two_prod(a[0], b[2]);
+        let (p4, q4) = two_prod(a[1], b[1]);
+        let (p5, q5) = two_prod(a[2], b[0]);
+
+        let (p1, p2, q0) = three_sum(p1, p2, q0);
+        let (p2, q1, q2) = three_sum(p2, q1, q2);
+        let (p3, p4, p5) = three_sum(p3, p4, p5);
+
+        let (s0, t0) = two_sum(p2, p3);
+        let (s1, t1) = two_sum(q1, p4);
+        let s2 = q2 + p5;
+        let (s1, t0) = two_sum(s1, t0);
+        let s2 = s2 + (t0 + t1);
+
+        let s1 = s1 + (a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + q0 + q3 + q4 + q5);
+
+        QuadDouble::renormalize(p0, p1, s0, s1, s2)
+    }
+}
+
+impl Neg for QuadDouble {
+    type Output = QuadDouble;
+
+    fn neg(self) -> QuadDouble {
+        QuadDouble { x: [-self.x[0], -self.x[1], -self.x[2], -self.x[3]] }
+    }
+}
+
+impl Sub for QuadDouble {
+    type Output = QuadDouble;
+
+    fn sub(self, other: QuadDouble) -> QuadDouble {
+        self + -other
+    }
+}
+
+impl Real for QuadDouble {
+    fn from_f64(x: f64) -> QuadDouble {
+        QuadDouble::from(x)
+    }
+
+    fn from_mpfr(x: &Mpfr) -> QuadDouble {
+        QuadDouble::from(x)
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.x[0]
+    }
+
+    fn prec(&self) -> usize {
+        212
+    }
+
+    fn plus(&self, other: &QuadDouble) -> QuadDouble {
+        *self + *other
+    }
+
+    fn minus(&self, other: &QuadDouble) -> QuadDouble {
+        *self - *other
+    }
+
+    fn times(&self, other: &QuadDouble) -> QuadDouble {
+        *self * *other
+    }
+}
+
+impl PartialOrd for QuadDouble {
+    fn partial_cmp(&self, other: &QuadDouble) -> Option<Ordering> {
+        for i in 0..4 {
+            match self.x[i].partial_cmp(&other.x[i]) {
+                Some(Ordering::Equal) => {}
+                ordering => return ordering,
+            }
+        }
+        Some(Ordering::Equal)
+    }
+}
+
+impl fmt::Display for QuadDouble {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Mpfr::from(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    fn relative_error(x: Mpfr, exact: &Mpfr) -> f64 {
+        to_f64(&((x - exact) / exact)).abs()
+    }
+
+    #[test]
+    fn test_double_double_arithmetic() {
+        let a = Mpfr::new2_from_str(300, "1.234567890123456789012345678901234567890", 10).unwrap();
+        let b = Mpfr::new2_from_str(300, "-0.987654321098765432109876543210987654321", 10).unwrap();
+        let (x, y) = (DoubleDouble::from(&a), DoubleDouble::from(&b));
+
+        assert!(relative_error(Mpfr::from(x + y), &(&a + &b)) < 1e-30);
+        assert!(relative_error(Mpfr::from(x - y), &(&a - &b)) < 1e-30);
+        assert!(relative_error(Mpfr::from(x * y), &(&a * &b)) < 1e-30);
+        assert!(y < x);
+    }
+
+    #[test]
+    fn test_quad_double_arithmetic() {
+        let a = Mpfr::new2_from_str(300,
+                                    "1.23456789012345678901234567890123456789012345678901234567890123456789",
+                                    10)
+            .unwrap();
+        let b = Mpfr::new2_from_str(300,
+                                    "-0.98765432109876543210987654321098765432109876543210987654321098765432",
+                                    10)
+            .unwrap();
+        let (x, y) = (QuadDouble::from(&a), QuadDouble::from(&b));
+
+        assert!(relative_error(Mpfr::from(x + y), &(&a + &b)) < 1e-60);
+        assert!(relative_error(Mpfr::from(x - y), &(&a - &b)) < 1e-60);
+        assert!(relative_error(Mpfr::from(x * y), &(&a * &b)) < 1e-60);
+        assert!(y < x);
+    }
+
+    #[test]
+    fn test_iterate() {
+        for &(x, y) in &[(-0.5, 0.0), (0.3, 0.1), (-1.8, 0.01), (0.25, 0.5)] {
+            let expected = iterate::<f64>(x, y, 100);
+            assert_eq!(iterate::<DoubleDouble>(DoubleDouble::from(x), DoubleDouble::from(y), 100),
+                       expected);
+            assert_eq!(iterate::<QuadDouble>(QuadDouble::from(x), QuadDouble::from(y), 100),
+                       expected);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use num::complex::Complex64;
+use rust_mpfr::mpfr::*;
+use super::{CanvasSize, FloatExp, Real, render};
+
+/// A converged orbit is on a root if it is this close to the `f64` one.
+const ROOT_TOLERANCE: f64 = 1e-6;
+
+/// Polynomial with complex coefficients.
+#[derive(Clone, Debug)]
+pub struct Polynomial {
+    coefficients: Vec<Complex64>,
+    roots: Vec<Complex64>,
+}
+
+impl Polynomial {
+    /// From the coefficients, constant term first. The degree must be at least one.
+    pub fn new(coefficients: Vec<Complex64>) -> Polynomial {
+        let mut coefficients = coefficients;
+        while coefficients.last() == Some(&Complex64::new(0.0, 0.0)) {
+            coefficients.pop();
+        }
+        assert!(coefficients.len() >= 2, "constant polynomial");
+
+        let roots = find_roots(&coefficients);
+        Polynomial {
+            coefficients: coefficients,
+            roots: roots,
+        }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    /// All roots with multiplicity, in no particular order.
+    pub fn roots(&self) -> &[Complex64] {
+        &self.roots
+    }
+
+    /// `p(z)` and `p'(z)` by Horner's scheme.
+    fn eval<T: Real>(&self, x: &T, y: &T) -> ((T, T), (T, T)) {
+        let n = self.degree();
+        let (mut px, mut py) = (T::from_f64(self.coefficients[n].re),
+                                T::from_f64(self.coefficients[n].im));
+        let (mut dx, mut dy) = (T::from_f64(0.0), T::from_f64(0.0));
+
+        for a in self.coefficients[..n].iter().rev() {
+            let dxtemp = (dx.times(x) - dy.times(y)).plus(&px);
+            dy = (dx.times(y) + dy.times(x)).plus(&py);
+            dx = dxtemp;
+
+            let pxtemp = (px.times(x) - py.times(y)).plus(&T::from_f64(a.re));
+            py = (px.times(y) + py.times(x)).plus(&T::from_f64(a.im));
+            px = pxtemp;
+        }
+        ((px, py), (dx, dy))
+    }
+}
+
+/// The roots by the Durand-Kerner method, which refines approximations of all
+/// of them at once.
+fn find_roots(coefficients: &[Complex64]) -> Vec<Complex64> {
+    let n = coefficients.len() - 1;
+    let monic: Vec<Complex64> = coefficients.iter().map(|&a| a / coefficients[n]).collect();
+    let eval = |z: Complex64| monic.iter().rev().fold(Complex64::new(0.0, 0.0), |p, &a| p * z + a);
+
+    let mut roots: Vec<Complex64> =
+        (0..n).map(|k| Complex64::new(0.4, 0.9).powf(k as f64)).collect();
+    for _ in 0..1000 {
+        let mut change = 0.0;
+        for i in 0..n {
+            let denominator = (0..n)
+                .filter(|&j| j != i)
+                .fold(Complex64::new(1.0, 0.0), |d, j| d * (roots[i] - roots[j]));
+            let step = eval(roots[i]) / denominator;
+            let root = roots[i] - step;
+
+            roots[i] = root;
+            change += step.norm_sqr();
+        }
+        if change < 1e-30 {
+            break;
+        }
+    }
+    roots
+}
+
+/// How the orbit of a pixel ended up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Convergence {
+    /// Settled after a smooth number of iterations, on the root with the given
+    /// index if it settled on one.
+    Converged(Option<usize>, f64),
+    /// Still moving after `max_iterations`.
+    Unconverged,
+}
+
+/// Iterates `z ↦ z - relaxation · p(z) / p'(z) + c` from `z0`. With `c = 0`
+/// and a relaxation of 1 this is Newton's method.
+pub fn iterate_newton<T: Real>(polynomial: &Polynomial,
+                               relaxation: Complex64,
+                               x0: T,
+                               y0: T,
+                               cx: &T,
+                               cy: &T,
+                               max_iterations: u32)
+                               -> Convergence {
+    let (rx, ry) = (T::from_f64(relaxation.re), T::from_f64(relaxation.im));
+    // Newton's method converges quadratically, so once the steps drop below
+    // `2^(-prec / 2)` the orbit is on the root to the precision of `T`.
+    let prec = x0.prec().max(cx.prec());
+    let tolerance_sqr = T::from_mpfr(&FloatExp::new(1.0, -(prec as i64)).to_mpfr(53));
+    let (mut x, mut y) = (x0, y0);
+    let mut previous_step_sqr = T::from_f64(0.0);
+
+    for i in 1..max_iterations + 1 {
+        let ((px, py), (dx, dy)) = polynomial.eval(&x, &y);
+        let norm_sqr = T::norm_sqr(&dx, &dy);
+        // `|p'|²` may be far below the range of `f64` without being zero.
+        if norm_sqr.exponent() == i64::MIN {
+            return Convergence::Unconverged;
+        }
+
+        // p / p' = p conj(p') / |p'|²
+        let recip = norm_sqr.recip();
+        let qx = (px.times(&dx) + py.times(&dy)).times(&recip);
+        let qy = (py.times(&dx) - px.times(&dy)).times(&recip);
+        let step_x = rx.times(&qx) - ry.times(&qy);
+        let step_y = rx.times(&qy) + ry.times(&qx);
+        x = x.minus(&step_x).plus(cx);
+        y = y.minus(&step_y).plus(cy);
+
+        // The step of the Nova fractal includes `c`.
+        let step_sqr = T::norm_sqr(&step_x.minus(cx), &step_y.minus(cy));
+        if step_sqr < tolerance_sqr {
+            // How far through the last step the orbit passed the tolerance, on
+            // a log scale.
+            let fraction = if i == 1 {
+                1.0
+            } else {
+                let previous = log2(&previous_step_sqr);
+                (-(prec as f64) - previous) / (log2(&step_sqr) - previous)
+            };
+            let root = polynomial.roots.iter().position(|r| {
+                T::norm_sqr(&x.minus(&T::from_f64(r.re)), &y.minus(&T::from_f64(r.im)))
+                    .lt_f64(ROOT_TOLERANCE * ROOT_TOLERANCE)
+            });
+
+            return Convergence::Converged(root, i as f64 - 1.0 + fraction);
+        }
+        previous_step_sqr = step_sqr;
+    }
+
+    Convergence::Unconverged
+}
+
+/// `log2(x)` of a non-negative `x`, which may be beyond the range of `f64`.
+fn log2<T: Real>(x: &T) -> f64 {
+    let e = x.exponent();
+    if e == i64::MIN {
+        return f64::NEG_INFINITY;
+    }
+    let scale = T::from_mpfr(&FloatExp::new(1.0, -e).to_mpfr(53));
+    x.times(&scale).to_f64().log2() + e as f64
+}
+
+/// Renders the Newton fractal of `polynomial` in `T`, every pixel being the
+/// starting `z`.
+pub fn calculate_all_newton<T: Real>(canvas_size: CanvasSize,
+                                     polynomial: &Polynomial,
+                                     max_iterations: u32)
+                                     -> Vec<Convergence> {
+    let zero = T::from_f64(0.0);
+    let one = Complex64::new(1.0, 0.0);
+
+    render(&canvas_size, |x: T, y| {
+        iterate_newton(polynomial, one, x, y, &zero, &zero, max_iterations)
+    })
+}
+
+/// Renders the Nova fractal of `polynomial` in `T`, every pixel being `c`.
+/// The orbits start from `z0`, usually a root of `p''` such as 1 for `z³ - 1`.
+pub fn calculate_all_nova<T: Real>(canvas_size: CanvasSize,
+                                   polynomial: &Polynomial,
+                                   relaxation: Complex64,
+                                   z0: &[Mpfr; 2],
+                                   max_iterations: u32)
+                                   -> Vec<Convergence> {
+    let x0 = T::from_mpfr(&z0[0]);
+    let y0 = T::from_mpfr(&z0[1]);
+
+    render(&canvas_size, |x: T, y| {
+        iterate_newton(polynomial, relaxation, x0.clone(), y0.clone(), &x, &y, max_iterations)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    fn cube_minus_one() -> Polynomial {
+        Polynomial::new(vec![Complex64::new(-1.0, 0.0),
+                             Complex64::new(0.0, 0.0),
+                             Complex64::new(0.0, 0.0),
+                             Complex64::new(1.0, 0.0),
+                             Complex64::new(0.0, 0.0)])
+    }
+
+    fn root_index(p: &Polynomial, z: Complex64) -> usize {
+        p.roots().iter().position(|r| (r - z).norm() < 1e-12).unwrap()
+    }
+
+    #[test]
+    fn test_roots() {
+        let p = cube_minus_one();
+        let third = Complex64::from_polar(&1.0, &(2.0 * ::std::f64::consts::PI / 3.0));
+
+        assert_eq!(p.degree(), 3);
+        for &z in &[Complex64::new(1.0, 0.0), third, third.conj()] {
+            root_index(&p, z);
+        }
+
+        let ((px, py), (dx, dy)) = p.eval(&2.0, &1.0);
+        assert_eq!((px, py), (1.0, 11.0));
+        assert_eq!((dx, dy), (9.0, 12.0));
+    }
+
+    #[test]
+    fn test_iterate_newton() {
+        let p = cube_minus_one();
+        let one = Complex64::new(1.0, 0.0);
+        let newton = |x: f64, y: f64| iterate_newton(&p, one, x, y, &0.0, &0.0, 100);
+
+        assert_eq!(newton(1.0, 0.0), Convergence::Converged(Some(root_index(&p, one)), 1.0));
+        match newton(2.0, 0.0) {
+            Convergence::Converged(root, i) => {
+                assert_eq!(root, Some(root_index(&p, one)));
+                assert!(i > 5.0 && i < 7.0);
+            }
+            c => panic!("{:?}", c),
+        }
+        match newton(-1.0, 1.0) {
+            Convergence::Converged(root, _) => {
+                assert_eq!(root, Some(root_index(&p, Complex64::new(-0.5, 0.75f64.sqrt()))));
+            }
+            c => panic!("{:?}", c),
+        }
+        assert_eq!(newton(0.0, 0.0), Convergence::Unconverged);
+    }
+
+    #[test]
+    fn test_iterate_newton_below_f64() {
+        // `z²` has its double root at 0, and `|p'(z)|²` underflows `f64` here.
+        let p = Polynomial::new(vec![Complex64::new(0.0, 0.0),
+                                     Complex64::new(0.0, 0.0),
+                                     Complex64::new(1.0, 0.0)]);
+        let one = Complex64::new(1.0, 0.0);
+        let x = Mpfr::new2_from_str(128, "1e-200", 10).unwrap();
+        let zero = mpfr!(0.0);
+
+        let mpfr = iterate_newton(&p, one, x.clone(), zero.clone(), &zero, &zero, 100);
+        assert_eq!(mpfr, Convergence::Converged(Some(0), 1.0));
+        let (x, zero) = (FloatExp::from(&x), FloatExp::from(0.0));
+        assert_eq!(iterate_newton(&p, one, x, zero, &zero, &zero, 100), mpfr);
+    }
+
+    #[test]
+    fn test_newton_f64_matches_mpfr() {
+        let c = CanvasSize::new_from_center(30, 20, [mpfr!(0.0), mpfr!(0.0)], mpfr!(1.0))
+            .set_prec(128);
+        let p = cube_minus_one();
+        let f64 = calculate_all_newton::<f64>(c.clone(), &p, 100);
+        let mpfr = calculate_all_newton::<Mpfr>(c, &p, 100);
+
+        let roots = |v: &[Convergence]| {
+            v.iter()
+                .map(|c| match *c {
+                    Convergence::Converged(root, _) => root,
+                    Convergence::Unconverged => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(roots(&f64), roots(&mpfr));
+        for k in 0..3 {
+            assert!(roots(&f64).contains(&Some(k)));
+        }
+    }
+
+    #[test]
+    fn test_newton_deep_zoom() {
+        // Newton's method maps this point onto the zero of `p'`, so every basin
+        // reaches it. Orbits from near it are thrown far out, and take hundreds
+        // of iterations to come back.
+        let x = Mpfr::new2_from_str(128, "-0.7937005259840997373758528196361541301957", 10)
+            .unwrap();
+        let c = CanvasSize::new_from_center(30, 20, [x, mpfr!(0.0)], mpfr!(1e20));
+        let c = c.set_prec(c.required_prec());
+        let v = calculate_all_newton::<Mpfr>(c, &cube_minus_one(), 500);
+
+        for k in 0..3 {
+            assert!(v.iter().any(|c| match *c {
+                Convergence::Converged(root, _) => root == Some(k),
+                Convergence::Unconverged => false,
+            }));
+        }
+    }
+
+    #[test]
+    fn test_nova() {
+        let p = cube_minus_one();
+        let z0 = [mpfr!(1.0), mpfr!(0.0)];
+        let c = CanvasSize::new_from_center(30, 20, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+        let v = calculate_all_nova::<f64>(c, &p, Complex64::new(1.0, 0.0), &z0, 200);
+
+        // For c = 0 the orbit starts on the root 1, elsewhere the fixed points
+        // are no roots.
+        assert_eq!(iterate_newton(&p, Complex64::new(1.0, 0.0), 1.0, 0.0, &0.0, &0.0, 200),
+                   Convergence::Converged(Some(root_index(&p, Complex64::new(1.0, 0.0))), 1.0));
+        let roots: Vec<Option<usize>> = v.iter()
+            .filter_map(|c| match *c {
+                Convergence::Converged(root, _) => Some(root),
+                Convergence::Unconverged => None,
+            })
+            .collect();
+        assert!(roots.contains(&None));
+        assert!(roots.len() < v.len());
+    }
+}
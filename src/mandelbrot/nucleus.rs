@@ -0,0 +1,336 @@
+use num::complex::Complex64;
+use rust_mpfr::mpfr::*;
+use super::{BAILOUT, CanvasSize, Iteration, Real, iterate_with, with_prec};
+use super::floatexp::{ComplexExp, FloatExp};
+
+/// Newton's method gives up after this many steps.
+const MAX_NEWTON_STEPS: u32 = 64;
+
+/// The iteration at which the orbit of `c` comes closest to 0 before it
+/// escapes or `max_iterations` is reached, the period of the atom domain `c`
+/// lies in. A good guess for the period of the nearest minibrot.
+///
+/// Orbits found periodic give their period instead, as they keep creeping
+/// closer to 0 at its multiples.
+pub fn atom_domain_period<T: Real>(x0: T, y0: T, max_iterations: u32) -> u32 {
+    let mut period = 1;
+    let mut min = f64::INFINITY;
+    let mut i = 0;
+
+    let result = iterate_with(&x0, &y0, max_iterations, |x, y| {
+            i += 1;
+            let norm_sqr = T::norm_sqr(x, y).to_f64();
+            if norm_sqr < min {
+                min = norm_sqr;
+                period = i;
+            }
+        })
+        .0;
+
+    match result {
+        Iteration::Periodic(p) => p,
+        _ => period,
+    }
+}
+
+/// The lowest period at which the critical orbit of the whole canvas, iterated
+/// in ball arithmetic, gets a ball around 0. A nucleus of that period most
+/// likely lies in the canvas.
+///
+/// The ball is centred on the orbit of the canvas center in MPFR, its radius
+/// bounds how far the orbits of the other points can have drifted. Returns
+/// `None` if the ball escapes first or `max_iterations` is reached.
+pub fn find_period(canvas_size: &CanvasSize, max_iterations: u32) -> Option<u32> {
+    let c = canvas_size.center();
+    let (width, height) = (canvas_size.width(), canvas_size.height());
+    let rc = FloatExp::from(&(width.square() + height.square()).sqrt()) / FloatExp::from(2.0);
+
+    let zero = with_prec(&Mpfr::from(0.0), canvas_size.get_prec());
+    let (mut x, mut y) = (zero.clone(), zero);
+    let mut norm = FloatExp::from(0.0);
+    let mut r = FloatExp::from(0.0);
+
+    for i in 1..max_iterations + 1 {
+        // |z² + c - (Z² + C)| <= |z - Z| (2|Z| + |z - Z|) + |c - C|
+        r = r * (FloatExp::from(2.0) * norm + r) + rc;
+
+        let xtemp = (x.square() - y.square()).plus(&c[0]);
+        y = x.times(&y).double().plus(&c[1]);
+        x = xtemp;
+        norm = FloatExp::from(&Mpfr::norm_sqr(&x, &y).sqrt());
+
+        if norm <= r {
+            return Some(i);
+        }
+        if norm - r > FloatExp::from(BAILOUT) {
+            return None;
+        }
+    }
+
+    None
+}
+
+/// Runs Newton's method from `c` in its precision. `f` gives the value and
+/// the derivative of the function whose root is wanted at a given `c`.
+fn newton<F>(c: &[Mpfr; 2], f: F) -> Option<[Mpfr; 2]>
+    where F: Fn(&Mpfr, &Mpfr) -> ([Mpfr; 2], [Mpfr; 2])
+{
+    let prec = ::std::cmp::max(c[0].get_prec(), c[1].get_prec());
+    let mut cx = with_prec(&c[0], prec);
+    let mut cy = with_prec(&c[1], prec);
+
+    for _ in 0..MAX_NEWTON_STEPS {
+        let ([x, y], [dx, dy]) = f(&cx, &cy);
+
+        let norm_sqr = Mpfr::norm_sqr(&dx, &dy);
+        if norm_sqr.is_zero() {
+            return None;
+        }
+        let step_x = (x.times(&dx) + y.times(&dy)) / &norm_sqr;
+        let step_y = (y.times(&dx) - x.times(&dy)) / &norm_sqr;
+        cx = cx.minus(&step_x);
+        cy = cy.minus(&step_y);
+
+        if !Mpfr::norm_sqr(&cx, &cy).lt_f64(16.0) {
+            return None;
+        }
+        // The step has shrunk to the last few bits of `c`.
+        let tolerance = ::std::cmp::max(cx.exponent(), cy.exponent()) - prec as i64 + 8;
+        if ::std::cmp::max(step_x.exponent(), step_y.exponent()) < tolerance {
+            return Some([cx, cy]);
+        }
+    }
+
+    None
+}
+
+/// `z_n(c)` and `dz_n/dc` for `n = iterations`, starting from `z_0 = 0`.
+fn iterate_with_derivative(cx: &Mpfr, cy: &Mpfr, iterations: u32) -> ([Mpfr; 2], [Mpfr; 2]) {
+    let zero = with_prec(&Mpfr::from(0.0), cx.get_prec());
+    let one = with_prec(&Mpfr::from(1.0), cx.get_prec());
+    let (mut x, mut y) = (zero.clone(), zero.clone());
+    let (mut dx, mut dy) = (zero.clone(), zero);
+
+    for _ in 0..iterations {
+        // dz/dc ↦ 2 z dz/dc + 1
+        let dxtemp = (x.times(&dx) - y.times(&dy)).double().plus(&one);
+        dy = (x.times(&dy) + y.times(&dx)).double();
+        dx = dxtemp;
+
+        let xtemp = (x.square() - y.square()).plus(cx);
+        y = x.times(&y).double().plus(cy);
+        x = xtemp;
+    }
+    ([x, y], [dx, dy])
+}
+
+/// Finds the nucleus of a hyperbolic component of `period` near `c`: the
+/// root of `z_period(c) = 0`, by Newton's method in the precision of `c`.
+///
+/// May converge to a nucleus whose period divides `period`. Returns `None`
+/// if Newton's method does not converge.
+pub fn find_nucleus(c: &[Mpfr; 2], period: u32) -> Option<[Mpfr; 2]> {
+    newton(c, |cx, cy| iterate_with_derivative(cx, cy, period))
+}
+
+/// Finds the Misiurewicz point near `c` whose critical orbit is periodic with
+/// `period` after `preperiod` iterations: the root of `z_(preperiod + period)(c)
+/// = z_preperiod(c)`, by Newton's method in the precision of `c`.
+///
+/// May converge to a point with a lower preperiod, or a period dividing
+/// `period`. Returns `None` if Newton's method does not converge.
+pub fn find_misiurewicz(c: &[Mpfr; 2], preperiod: u32, period: u32) -> Option<[Mpfr; 2]> {
+    newton(c, |cx, cy| {
+        let (z, dz) = iterate_with_derivative(cx, cy, preperiod + period);
+        let (zq, dzq) = iterate_with_derivative(cx, cy, preperiod);
+
+        ([&z[0] - &zq[0], &z[1] - &zq[1]], [&dz[0] - &dzq[0], &dz[1] - &dzq[1]])
+    })
+}
+
+/// A canvas centred on the Misiurewicz point near `c` at `zoom`, with the
+/// point refined in the precision the canvas requires.
+pub fn misiurewicz_canvas(pixel_width: u32,
+                          pixel_height: u32,
+                          c: &[Mpfr; 2],
+                          preperiod: u32,
+                          period: u32,
+                          zoom: Mpfr)
+                          -> Option<CanvasSize> {
+    let prec = CanvasSize::new_centered(pixel_width, pixel_height, c.clone(), zoom.clone())
+        .get_prec();
+    let c = [with_prec(&c[0], prec), with_prec(&c[1], prec)];
+
+    find_misiurewicz(&c, preperiod, period)
+        .map(|m| CanvasSize::new_centered(pixel_width, pixel_height, m, zoom))
+}
+
+/// Estimates the size of the minibrot of `period` at `nucleus`. The minibrot
+/// is roughly the whole set scaled and rotated by the returned factor, so its
+/// cusp lies near `nucleus + size / 4`.
+pub fn minibrot_size(nucleus: &[Mpfr; 2], period: u32) -> ComplexExp {
+    let one = ComplexExp::from(Complex64::new(1.0, 0.0));
+    let (mut x, mut y) = (nucleus[0].clone(), nucleus[1].clone());
+    let mut l = one;
+    let mut b = one;
+
+    for _ in 1..period {
+        let z = Complex64::new(x.to_f64(), y.to_f64());
+        l = ComplexExp::from(z * 2.0) * l;
+        b = b + one / l;
+
+        let xtemp = (x.square() - y.square()).plus(&nucleus[0]);
+        y = x.times(&y).double().plus(&nucleus[1]);
+        x = xtemp;
+    }
+
+    one / (b * l * l)
+}
+
+/// A canvas of the same pixel size framing the minibrot of `period` nearest to
+/// `c`, like the initial view frames the whole set. The nucleus is refined
+/// again if the new canvas needs more precision than `canvas`.
+pub fn zoom_to_minibrot(canvas: &CanvasSize, c: &[Mpfr; 2], period: u32) -> Option<CanvasSize> {
+    let prec = canvas.get_prec();
+    let mut nucleus = find_nucleus(&[with_prec(&c[0], prec), with_prec(&c[1], prec)], period);
+
+    while let Some(n) = nucleus {
+        let framed = canvas.frame_minibrot(&n, minibrot_size(&n, period));
+        let prec = framed.get_prec();
+
+        if prec <= n[0].get_prec() {
+            return Some(framed);
+        }
+        nucleus = find_nucleus(&[with_prec(&n[0], prec), with_prec(&n[1], prec)], period);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    fn nucleus(x: f64, y: f64, period: u32) -> (f64, f64) {
+        let c = [Mpfr::new2_from_str(128, x.to_string(), 10).unwrap(),
+                 Mpfr::new2_from_str(128, y.to_string(), 10).unwrap()];
+        let n = find_nucleus(&c, period).unwrap();
+        (n[0].to_f64(), n[1].to_f64())
+    }
+
+    #[test]
+    fn test_find_nucleus() {
+        let close = |(x, y): (f64, f64), (ex, ey): (f64, f64)| {
+            (x - ex).abs() < 1e-15 && (y - ey).abs() < 1e-15
+        };
+
+        assert!(close(nucleus(0.1, 0.1, 1), (0.0, 0.0)));
+        assert!(close(nucleus(-0.9, 0.05, 2), (-1.0, 0.0)));
+        assert!(close(nucleus(-1.76, 0.0, 3), (-1.7548776662466927, 0.0)));
+        assert!(close(nucleus(-0.12, 0.74, 3), (-0.1225611668766536, 0.7448617666197442)));
+    }
+
+    #[test]
+    fn test_minibrot_size() {
+        let whole = minibrot_size(&[mpfr!(0.0), mpfr!(0.0)], 1);
+        assert_eq!(whole.to_complex64(), Complex64::new(1.0, 0.0));
+
+        // The period 3 minibrot on the real axis has its cusp at -7/4.
+        let c = [Mpfr::new2_from_str(128, "-1.76", 10).unwrap(), mpfr!(0.0)];
+        let n = find_nucleus(&c, 3).unwrap();
+        let size = minibrot_size(&n, 3).to_complex64();
+
+        assert!(size.re > 0.018 && size.re < 0.02 && size.im == 0.0);
+        assert!((n[0].to_f64() + size.re / 4.0 + 1.75).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_atom_domain_period() {
+        assert_eq!(atom_domain_period::<f64>(0.1, 0.1, 1000), 1);
+        assert_eq!(atom_domain_period::<f64>(-1.76, 0.0, 1000), 3);
+        assert_eq!(atom_domain_period::<f64>(-0.12, 0.74, 1000), 3);
+        // Escape just outside the period 3 minibrot.
+        assert_eq!(atom_domain_period::<f64>(-1.7549, 0.02, 1000), 3);
+        assert_eq!(atom_domain_period::<f64>(-1.745, 0.005, 1000), 3);
+    }
+
+    fn period(x: &str, y: &str, zoom: f64) -> Option<u32> {
+        let center = [Mpfr::new2_from_str(128, x, 10).unwrap(),
+                      Mpfr::new2_from_str(128, y, 10).unwrap()];
+        find_period(&CanvasSize::new_from_center(60, 40, center, Mpfr::from(zoom)), 1000)
+    }
+
+    #[test]
+    fn test_find_period() {
+        assert_eq!(period("-0.5", "0", 1.0), Some(1));
+        assert_eq!(period("-1.0", "0.01", 100.0), Some(2));
+        assert_eq!(period("-1.7549", "0", 1000.0), Some(3));
+        assert_eq!(period("-0.1226", "0.7449", 1000.0), Some(3));
+        assert_eq!(period("-0.1565", "1.0322", 1000.0), Some(4));
+        assert_eq!(period("1.0", "1.0", 100.0), None);
+    }
+
+    #[test]
+    fn test_find_period_feeds_nucleus() {
+        // A period 39 minibrot in the seahorse valley.
+        let n = [Mpfr::new2_from_str(128, "-0.74364230165788594611859731943377783587", 10).unwrap(),
+                 Mpfr::new2_from_str(128, "0.13182651981259472349919301230551915131", 10).unwrap()];
+
+        for &zoom in &[1e5, 1e7] {
+            let center = [&n[0] + 2.0 / zoom, &n[1] - 1.0 / zoom];
+            let canvas = CanvasSize::new_from_center(60, 40, center, Mpfr::from(zoom));
+            let p = find_period(&canvas, 10000);
+            let found = find_nucleus(&canvas.center(), p.unwrap()).unwrap();
+
+            assert_eq!(p, Some(39));
+            assert!((&found[0] - &n[0]).to_f64().abs() < 1e-30);
+            assert!((&found[1] - &n[1]).to_f64().abs() < 1e-30);
+        }
+    }
+
+    fn misiurewicz(x: &str, y: &str, preperiod: u32, period: u32) -> (f64, f64) {
+        let c = [Mpfr::new2_from_str(128, x, 10).unwrap(),
+                 Mpfr::new2_from_str(128, y, 10).unwrap()];
+        let m = find_misiurewicz(&c, preperiod, period).unwrap();
+        (m[0].to_f64(), m[1].to_f64())
+    }
+
+    #[test]
+    fn test_find_misiurewicz() {
+        // The orbit of i is 0, i, -1 + i, -i, -1 + i, ...
+        let (x, y) = misiurewicz("0.05", "0.95", 2, 2);
+        assert!(x.abs() < 1e-30 && (y - 1.0).abs() < 1e-15);
+
+        // The tip of the antenna, 0, -2, 2, 2, ...
+        let (x, y) = misiurewicz("-1.98", "0.01", 2, 1);
+        assert!((x + 2.0).abs() < 1e-15 && y.abs() < 1e-30);
+
+        // The first branch point of the antenna, c³ + 2c² + 2c + 2 = 0.
+        let (x, y) = misiurewicz("-1.55", "0", 3, 1);
+        assert!((x + 1.5436890126920764).abs() < 1e-15 && y == 0.0);
+    }
+
+    #[test]
+    fn test_misiurewicz_canvas() {
+        let c = [mpfr!(0.01), mpfr!(1.02)];
+        let canvas = misiurewicz_canvas(60, 40, &c, 2, 2, mpfr!(1e40)).unwrap();
+        let center = canvas.center();
+
+        assert!(canvas.get_prec() > 128);
+        assert!((&center[0] * 1e40).to_f64().abs() < 1e-6);
+        assert!(((&center[1] - 1.0) * 1e40).to_f64().abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_zoom_to_minibrot() {
+        let canvas = CanvasSize::new_from_center(60, 40, [mpfr!(-0.5), mpfr!(0.0)], mpfr!(1.0));
+        let c = [mpfr!(-1.76), mpfr!(0.0)];
+        let framed = zoom_to_minibrot(&canvas, &c, 3).unwrap();
+        let center = framed.center();
+
+        assert!((framed.get_zoom().to_f64() - 52.5).abs() < 1.0);
+        assert!((center[0].to_f64() + 1.7644).abs() < 1e-3);
+        assert_eq!(center[1].to_f64(), 0.0);
+        assert!(framed.get_prec() >= canvas.get_prec());
+    }
+}
@@ -0,0 +1,215 @@
+use rust_mpfr::mpfr::*;
+use std::fmt::Display;
+use std::ops::{Add, Mul, Neg, Sub};
+use super::{FloatExp, to_f64, with_prec};
+
+/// Real number type the iteration is generic over.
+///
+/// The operators consume their operands; the methods taking references are what
+/// the iteration uses, so types like `Mpfr` don't have to be cloned.
+pub trait Real: Clone + PartialOrd + Display + Send + Sync
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Neg<Output = Self>
+{
+    fn from_f64(x: f64) -> Self;
+    fn from_mpfr(x: &Mpfr) -> Self;
+    fn to_f64(&self) -> f64;
+
+    /// Number of mantissa bits.
+    fn prec(&self) -> usize;
+
+    /// Binary exponent `e` with `2^(e - 1) <= |x| < 2^e`, `i64::MIN` for zero.
+    fn exponent(&self) -> i64 {
+        let x = self.to_f64();
+        if x == 0.0 {
+            i64::MIN
+        } else {
+            FloatExp::from(x).exponent()
+        }
+    }
+
+    fn plus(&self, other: &Self) -> Self;
+    fn minus(&self, other: &Self) -> Self;
+    fn times(&self, other: &Self) -> Self;
+
+    fn square(&self) -> Self {
+        self.times(self)
+    }
+
+    fn double(&self) -> Self {
+        self.plus(self)
+    }
+
+    fn abs(&self) -> Self {
+        if self.lt_f64(0.0) {
+            -self.clone()
+        } else {
+            self.clone()
+        }
+    }
+
+    /// `1 / self`, by Newton's method from the `f64` reciprocal. Every step
+    /// doubles the number of correct bits.
+    fn recip(&self) -> Self {
+        let two = Self::from_f64(2.0);
+        let mut y = Self::from_f64(1.0 / self.to_f64());
+        let mut bits = 48;
+        while bits < self.prec() {
+            y = y.times(&two.minus(&self.times(&y)));
+            bits *= 2;
+        }
+        y
+    }
+
+    fn norm_sqr(x: &Self, y: &Self) -> Self {
+        x.square() + y.square()
+    }
+
+    fn lt_f64(&self, c: f64) -> bool {
+        *self < Self::from_f64(c)
+    }
+}
+
+impl Real for f64 {
+    fn from_f64(x: f64) -> f64 {
+        x
+    }
+
+    fn from_mpfr(x: &Mpfr) -> f64 {
+        to_f64(x)
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    fn prec(&self) -> usize {
+        53
+    }
+
+    fn plus(&self, other: &f64) -> f64 {
+        self + other
+    }
+
+    fn minus(&self, other: &f64) -> f64 {
+        self - other
+    }
+
+    fn times(&self, other: &f64) -> f64 {
+        self * other
+    }
+
+    fn recip(&self) -> f64 {
+        1.0 / self
+    }
+
+    fn lt_f64(&self, c: f64) -> bool {
+        *self < c
+    }
+}
+
+impl Real for Mpfr {
+    fn from_f64(x: f64) -> Mpfr {
+        Mpfr::from(x)
+    }
+
+    fn from_mpfr(x: &Mpfr) -> Mpfr {
+        x.clone()
+    }
+
+    fn to_f64(&self) -> f64 {
+        to_f64(self)
+    }
+
+    fn prec(&self) -> usize {
+        self.get_prec()
+    }
+
+    fn exponent(&self) -> i64 {
+        let x = to_f64(self);
+        if x.is_finite() && x.abs() > 1e-300 {
+            FloatExp::from(x).exponent()
+        } else if *self == Mpfr::from(0.0) {
+            i64::MIN
+        } else {
+            FloatExp::from(self).exponent()
+        }
+    }
+
+    fn plus(&self, other: &Mpfr) -> Mpfr {
+        self + other
+    }
+
+    fn minus(&self, other: &Mpfr) -> Mpfr {
+        self - other
+    }
+
+    fn times(&self, other: &Mpfr) -> Mpfr {
+        self * other
+    }
+
+    fn recip(&self) -> Mpfr {
+        with_prec(&Mpfr::from(1.0), self.get_prec()) / self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    fn norm_of_three_four<T: Real>() -> T {
+        T::norm_sqr(&T::from_f64(3.0), &T::from_f64(-4.0))
+    }
+
+    #[test]
+    fn test_operations() {
+        assert_eq!(norm_of_three_four::<f64>(), 25.0);
+        assert_eq!(norm_of_three_four::<Mpfr>(), mpfr!(25.0));
+        assert_eq!(norm_of_three_four::<FloatExp>().to_f64(), 25.0);
+        assert_eq!(norm_of_three_four::<DoubleDouble>().to_f64(), 25.0);
+        assert_eq!(norm_of_three_four::<QuadDouble>().to_f64(), 25.0);
+
+        assert_eq!(mpfr!(1.5).double(), mpfr!(3.0));
+        assert!(mpfr!(3.9).lt_f64(4.0));
+        assert!(!mpfr!(4.0).lt_f64(4.0));
+        assert_eq!(Real::abs(&mpfr!(-1.5)), mpfr!(1.5));
+        assert_eq!(Real::abs(&DoubleDouble::from(2.5)).to_f64(), 2.5);
+    }
+
+    fn recip_error<T: Real>(x: T) -> i64 {
+        (x.times(&x.recip()) - T::from_f64(1.0)).exponent()
+    }
+
+    #[test]
+    fn test_recip() {
+        let x = Mpfr::new2_from_str(256, "3", 10).unwrap();
+
+        assert_eq!(4.0f64.recip(), 0.25);
+        assert_eq!(FloatExp::new(1.0, -3000).recip(), FloatExp::new(1.0, 3000));
+        assert!(recip_error(x.clone()) < -250);
+        assert!(recip_error(DoubleDouble::from_mpfr(&x)) < -100);
+        assert!(recip_error(QuadDouble::from_mpfr(&x)) < -200);
+    }
+
+    #[test]
+    fn test_prec() {
+        let x = Mpfr::new2_from_str(128, "0.5", 10).unwrap();
+
+        assert_eq!(0.5f64.prec(), 53);
+        assert_eq!(x.prec(), 128);
+        assert_eq!(DoubleDouble::from_mpfr(&x).prec(), 106);
+        assert_eq!(QuadDouble::from_mpfr(&x).prec(), 212);
+    }
+
+    #[test]
+    fn test_exponent() {
+        let tiny = Mpfr::new2_from_str(128, "1e-400", 10).unwrap();
+
+        assert_eq!(0.75f64.exponent(), 0);
+        assert_eq!((-3.0f64).exponent(), 2);
+        assert_eq!(0.0f64.exponent(), i64::MIN);
+        assert_eq!(mpfr!(0.75).exponent(), 0);
+        assert_eq!(tiny.exponent(), -1328);
+        assert_eq!(Mpfr::from(0.0).exponent(), i64::MIN);
+    }
+}
@@ -0,0 +1,240 @@
+use rayon;
+use rayon::prelude::*;
+use super::{CanvasSize, Pixels, Real, iterate_with_period};
+
+/// Tiles this narrow or narrower are iterated in full instead of split.
+const MIN_TILE: u32 = 4;
+
+/// The escape count of a pixel, `None` if it did not escape, and its smooth
+/// iteration count.
+type Value = (Option<u32>, Option<f64>);
+
+/// A rectangle of pixels, its border included.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Tile {
+    fn len(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    /// Index of a pixel of the tile, in coordinates relative to it.
+    fn idx(&self, p: [u32; 2]) -> usize {
+        (p[1] * self.width + p[0]) as usize
+    }
+
+    fn border(&self) -> Vec<[u32; 2]> {
+        let (w, h) = (self.width, self.height);
+        (0..h)
+            .flat_map(|y| (0..w).map(move |x| [x, y]))
+            .filter(|p| p[0] == 0 || p[1] == 0 || p[0] == w - 1 || p[1] == h - 1)
+            .collect()
+    }
+
+    /// Halves the tile across its longer side. The halves share the line
+    /// between them, which is returned as well.
+    fn split(&self) -> (Tile, Tile, Vec<[u32; 2]>) {
+        if self.width >= self.height {
+            let mid = self.width / 2;
+            (Tile { width: mid + 1, ..*self },
+             Tile {
+                x: self.x + mid,
+                width: self.width - mid,
+                ..*self
+            },
+             (1..self.height - 1).map(|y| [mid, y]).collect())
+        } else {
+            let mid = self.height / 2;
+            (Tile { height: mid + 1, ..*self },
+             Tile {
+                y: self.y + mid,
+                height: self.height - mid,
+                ..*self
+            },
+             (1..self.width - 1).map(|x| [x, mid]).collect())
+        }
+    }
+
+    /// Copies the values of `inner`, a part of this tile, out of `values`.
+    fn crop<V: Copy>(&self, values: &[V], inner: &Tile) -> Vec<V> {
+        (0..inner.height)
+            .flat_map(|y| (0..inner.width).map(move |x| [x, y]))
+            .map(|p| values[self.idx([inner.x - self.x + p[0], inner.y - self.y + p[1]])])
+            .collect()
+    }
+
+    /// Copies the values of `inner`, a part of this tile, into `values`.
+    fn paste<V: Copy>(&self, values: &mut [V], inner: &Tile, inner_values: &[V]) {
+        for y in 0..inner.height {
+            for x in 0..inner.width {
+                values[self.idx([inner.x - self.x + x, inner.y - self.y + y])] =
+                    inner_values[inner.idx([x, y])];
+            }
+        }
+    }
+}
+
+/// Evaluates `f` at the given pixels of `tile` in parallel and stores the
+/// results in `values`. Returns the number of pixels evaluated.
+fn evaluate<F>(f: &F, tile: &Tile, values: &mut [Option<Value>], pixels: &[[u32; 2]]) -> usize
+    where F: Fn([u32; 2]) -> Value + Sync
+{
+    let mut results: Vec<Value> = Vec::new();
+    (0..pixels.len() as u32)
+        .into_par_iter()
+        .weight_max()
+        .map(|i| {
+            let p = pixels[i as usize];
+            f([tile.x + p[0], tile.y + p[1]])
+        })
+        .collect_into(&mut results);
+
+    for (p, r) in pixels.iter().zip(results) {
+        values[tile.idx(*p)] = Some(r);
+    }
+    pixels.len()
+}
+
+/// Completes `values` of a tile whose border is known. If the whole border has
+/// the same escape count, so has the tile, otherwise the tile is split and both
+/// halves are filled in parallel. Returns the smooth iteration counts and the
+/// number of pixels evaluated.
+fn fill<F>(f: &F, tile: Tile, values: Vec<Option<Value>>) -> (Vec<Option<f64>>, usize)
+    where F: Fn([u32; 2]) -> Value + Sync
+{
+    let mut values = values;
+    let border: Vec<Value> = tile.border().iter().map(|&p| values[tile.idx(p)].unwrap()).collect();
+
+    if border.iter().all(|v| v.0 == border[0].0) {
+        // Only the fraction of the smooth count varies within an escape band.
+        // Filled pixels get the mean of the border, or `None` for interior tiles.
+        let mean = border.iter().map(|v| v.1).sum::<Option<f64>>().map(|s| s / border.len() as f64);
+        return (values.iter().map(|v| v.map_or(mean, |v| v.1)).collect(), 0);
+    }
+
+    if tile.width <= MIN_TILE || tile.height <= MIN_TILE {
+        let missing: Vec<[u32; 2]> = (0..tile.height)
+            .flat_map(|y| (0..tile.width).map(move |x| [x, y]))
+            .filter(|&p| values[tile.idx(p)].is_none())
+            .collect();
+        let evaluated = evaluate(f, &tile, &mut values, &missing);
+        return (values.into_iter().map(|v| v.unwrap().1).collect(), evaluated);
+    }
+
+    let (a, b, line) = tile.split();
+    let evaluated = evaluate(f, &tile, &mut values, &line);
+    let (a_values, b_values) = (tile.crop(&values, &a), tile.crop(&values, &b));
+    let ((a_values, a_evaluated), (b_values, b_evaluated)) =
+        rayon::join(|| fill(f, a, a_values), || fill(f, b, b_values));
+
+    let mut filled = vec![None; tile.len()];
+    tile.paste(&mut filled, &a, &a_values);
+    tile.paste(&mut filled, &b, &b_values);
+    (filled, evaluated + a_evaluated + b_evaluated)
+}
+
+/// Evaluates `f` by Mariani-Silver subdivision: the border of every tile is
+/// evaluated first, and a tile whose border has a single escape count is filled
+/// without evaluating it. Returns the smooth iteration counts and the number of
+/// pixels evaluated.
+fn render_subdivided<T, F>(canvas_size: &CanvasSize, f: F) -> (Vec<Option<f64>>, usize)
+    where T: Real,
+          F: Fn(T, T) -> Value + Sync
+{
+    let pixels = Pixels::new(canvas_size);
+    let f = |p: [u32; 2]| {
+        let (x, y) = pixels.point(p);
+        f(x, y)
+    };
+    let tile = Tile {
+        x: 0,
+        y: 0,
+        width: canvas_size.pixel_width,
+        height: canvas_size.pixel_height,
+    };
+
+    let mut values = vec![None; tile.len()];
+    let evaluated = evaluate(&f, &tile, &mut values, &tile.border());
+    let (values, filled_evaluated) = fill(&f, tile, values);
+    (values, evaluated + filled_evaluated)
+}
+
+/// Renders the canvas in `T` like `calculate_all`, but fills tiles whose
+/// border pixels all escape after the same number of iterations, or all do not
+/// escape, iterating only the interior of the others.
+///
+/// Since the set is connected and has no holes, and the points escaping after
+/// `n` iterations lie between two nested curves around it, a tile shares the
+/// escape count of its border as long as no filament slips between the pixels
+/// of the border. Escaping pixels of filled tiles get the mean smooth count of
+/// the border, so their fraction is approximate.
+pub fn calculate_all_subdivided<T: Real>(canvas_size: CanvasSize,
+                                         max_iterations: u32)
+                                         -> Vec<Option<f64>> {
+    render_subdivided(&canvas_size, |x: T, y| value(x, y, max_iterations)).0
+}
+
+fn value<T: Real>(x: T, y: T, max_iterations: u32) -> Value {
+    let iteration = iterate_with_period(x, y, max_iterations);
+    (iteration.escaped(), iteration.smooth())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    #[test]
+    fn test_split_tile() {
+        let tile = Tile {
+            x: 2,
+            y: 3,
+            width: 5,
+            height: 4,
+        };
+        let (a, b, line) = tile.split();
+
+        assert_eq!(a, Tile { width: 3, ..tile });
+        assert_eq!(b, Tile { x: 4, width: 3, ..tile });
+        assert_eq!(line, vec![[2, 1], [2, 2]]);
+        assert_eq!(tile.border().len(), 14);
+
+        let values: Vec<usize> = (0..tile.len()).collect();
+        let mut pasted = vec![0; tile.len()];
+        tile.paste(&mut pasted, &b, &tile.crop(&values, &b));
+        assert_eq!(pasted[tile.idx([4, 3])], 19);
+        assert_eq!(pasted[tile.idx([1, 3])], 0);
+    }
+
+    /// Whether `v` has the interior and escape counts of `brute_force`.
+    fn same_bands(v: &[Option<f64>], brute_force: &[Option<f64>]) -> bool {
+        v.iter().zip(brute_force).all(|(&a, &b)| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() < 1.0,
+            (a, b) => a == b,
+        })
+    }
+
+    #[test]
+    fn test_subdivided_matches_brute_force() {
+        // The initial view of the app.
+        let c = CanvasSize::new_from_center(400, 200, [mpfr!(-1.0), mpfr!(0.0)], mpfr!(1.0));
+        let (v, evaluated) = render_subdivided(&c, |x: f64, y| value(x, y, 1000));
+        let brute_force = calculate_all_f64(c.clone(), 1000);
+        assert!(same_bands(&v, &brute_force));
+
+        // Less than half the pixels are iterated, and many of the rest escape.
+        let interior = v.iter().filter(|i| i.is_none()).count();
+        let escaped = v.len() - interior;
+        assert!(evaluated * 2 < v.len());
+        assert!(v.len() - evaluated > interior + escaped / 4);
+
+        let c = CanvasSize::new_from_center(100, 50, [mpfr!(-1.0), mpfr!(0.0)], mpfr!(1.0));
+        assert!(same_bands(&calculate_all_subdivided::<Mpfr>(c.clone(), 1000),
+                           &calculate_all_mpfr(c, 1000)));
+    }
+}